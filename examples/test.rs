@@ -1,21 +1,20 @@
-use glam::{vec3, Mat3, Vec3};
+use glam::vec3;
 use gpu_raymarcher::{
-    cmd::{keyboard, mouse, render, time, window},
-    Callbacks, Context, KeyCode, KeyModifier, Shape,
+    cmd::{camera, gamepad, keyboard, mouse, render, time, window},
+    Callbacks, Context, FlyState, GamepadAxis, KeyCode, Shape,
 };
 
 const CAMERA_MOVE_SPEED: f32 = 1.0;
 const CAMERA_ROTATE_SPEED: f32 = 1.0;
 const CAMERA_ZOOM_SPEED: f32 = 0.01;
+const GAMEPAD_LOOK_SPEED: f32 = 120.0;
 
 struct App {
-    camera_pos: Vec3,
-    yaw: f32,
-    pitch: f32,
+    camera: FlyState,
     focal_len: f32,
     pause: bool,
-    tot_dt: f32,
     frames: u32,
+    accumulate: bool,
 }
 
 impl Callbacks for App {
@@ -24,18 +23,17 @@ impl Callbacks for App {
         window::set_vsync(ctx, false);
     }
 
+    // is_multiple_of needs rust 1.87
+    #[allow(clippy::manual_is_multiple_of)]
     fn update(&mut self, ctx: &mut Context, dt: f32) -> bool {
         self.input(ctx, dt);
         self.update(ctx);
 
-        self.tot_dt += dt;
         self.frames += 1;
         if self.frames % 50 == 0 {
-            let avg = self.tot_dt / self.frames as f32;
-            let fps = 1.0 / avg;
-            println!("avg ms: {avg}, avg fps: {fps}");
-            self.tot_dt = 0.0;
-            self.frames = 0;
+            let avg = time::frame_time(ctx);
+            let fps = time::fps(ctx);
+            window::set_title(ctx, &format!("avg ms: {avg:.4}, avg fps: {fps:.0}"));
         }
 
         false
@@ -53,56 +51,47 @@ impl App {
             return;
         }
 
-        // Camera rotation
-        let (dx, dy) = mouse::mouse_delta(ctx);
-        self.yaw += dx * CAMERA_ROTATE_SPEED;
-        self.pitch += dy * CAMERA_ROTATE_SPEED;
-
-        if self.pitch > 89.0 {
-            self.pitch = 89.0
-        }
-        if self.pitch < -89.0 {
-            self.pitch = -89.0
+        // Progressive accumulation, only clean while the camera and scene are still
+        if keyboard::key_just_pressed(ctx, KeyCode::T) {
+            self.accumulate = !self.accumulate;
+            render::set_accumulation(ctx, self.accumulate);
         }
-        let rotation = Mat3::from_rotation_y(self.yaw.to_radians())
-            * Mat3::from_rotation_x(self.pitch.to_radians());
-
-        // Camera movement
-        let mut move_speed = CAMERA_MOVE_SPEED;
-        if keyboard::modifier_pressed(ctx, KeyModifier::Shift) {
-            move_speed *= 3.0;
+        let (prev_camera, prev_focal_len) = (self.camera, self.focal_len);
+
+        // First gamepad, if any, looks with the right stick and moves with the left stick
+        if let Some(pad) = gamepad::gamepads(ctx).first().copied() {
+            self.camera.look(
+                gamepad::axis_value(ctx, pad, GamepadAxis::RightStickX) * GAMEPAD_LOOK_SPEED * dt,
+                -gamepad::axis_value(ctx, pad, GamepadAxis::RightStickY) * GAMEPAD_LOOK_SPEED * dt,
+            );
+            let rotation = self.camera.rotation();
+            let mut movement = rotation.z_axis
+                * gamepad::axis_value(ctx, pad, GamepadAxis::LeftStickY)
+                + rotation.x_axis * gamepad::axis_value(ctx, pad, GamepadAxis::LeftStickX);
+            if movement.length_squared() > 1.0 {
+                movement = movement.normalize();
+            }
+            self.camera.pos += movement * CAMERA_MOVE_SPEED * dt;
         }
 
-        let rot_mat = rotation.to_cols_array_2d();
-        let right = vec3(rot_mat[0][0], rot_mat[0][1], rot_mat[0][2]).normalize();
-        let up = vec3(rot_mat[1][0], rot_mat[1][1], rot_mat[1][2]).normalize();
-        let forward = vec3(rot_mat[2][0], rot_mat[2][1], rot_mat[2][2]).normalize();
-
-        let mut movement = Vec3::ZERO;
-        if keyboard::key_pressed(ctx, KeyCode::W) {
-            movement += forward;
-        }
-        if keyboard::key_pressed(ctx, KeyCode::S) {
-            movement -= forward;
-        }
-        if keyboard::key_pressed(ctx, KeyCode::D) {
-            movement += right;
-        }
-        if keyboard::key_pressed(ctx, KeyCode::A) {
-            movement -= right;
-        }
-        if movement != Vec3::ZERO {
-            movement = movement.normalize() * move_speed * dt;
-        }
-        self.camera_pos += movement;
+        // Keyboard and mouse
+        camera::fly_controller(
+            ctx,
+            &mut self.camera,
+            CAMERA_MOVE_SPEED,
+            CAMERA_ROTATE_SPEED,
+            dt,
+        );
 
         // Zoom
         self.focal_len += mouse::scroll_delta(ctx).1 * CAMERA_ZOOM_SPEED;
         self.focal_len = self.focal_len.max(0.1);
 
-        // Update renderer
-        render::set_camera_rot(ctx, rotation);
-        render::set_camera_pos(ctx, self.camera_pos);
+        // Restart accumulation so camera motion does not smear
+        if (self.camera, self.focal_len) != (prev_camera, prev_focal_len) {
+            render::reset_accumulation(ctx);
+        }
+
         render::set_focal_length(ctx, self.focal_len);
     }
 
@@ -145,15 +134,15 @@ impl App {
 
 fn main() {
     let app = App {
-        camera_pos: vec3(0.0, 0.0, -3.0),
-        yaw: 0.0,
-        pitch: 0.0,
+        camera: FlyState::new(vec3(0.0, 0.0, -3.0)),
         focal_len: 1.0,
         pause: false,
-        tot_dt: 0.0,
         frames: 0,
+        accumulate: false,
     };
-    gpu_raymarcher::run(app);
+    if let Err(err) = gpu_raymarcher::run(app) {
+        eprintln!("{err}");
+    }
 }
 
 // Camera rotation
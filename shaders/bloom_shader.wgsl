@@ -0,0 +1,65 @@
+// Extracts pixels above the bloom threshold and blurs them
+// Run as two separable passes, horizontal with extraction then vertical
+
+@group(0) @binding(0) var source: texture_2d<f32>;
+@group(0) @binding(1) var destination: texture_storage_2d<rgba16float, write>;
+@group(0) @binding(2) var<uniform> post: PostGlobals;
+
+// Must match PostGlobals in render.rs and the render shader
+struct PostGlobals {
+    focus_distance: f32,
+    aperture: f32,
+    bloom_threshold: f32,
+    bloom_intensity: f32,
+    outline_color: vec3<f32>,
+    outline_thickness: f32,
+};
+
+const radius: i32 = 12;
+const sigma: f32 = 5.0;
+
+// Workgroups must match BLOOM_WORKGROUP_SIZE, those on the right and bottom edges
+// can extend past the texture
+@compute @workgroup_size(8, 8)
+fn blur_x(@builtin(global_invocation_id) id: vec3<u32>) {
+    let dim = vec2<u32>(textureDimensions(destination));
+    if id.x >= dim.x || id.y >= dim.y {
+        return;
+    }
+    let pixel = vec2<i32>(id.xy);
+    textureStore(destination, pixel, vec4<f32>(blur(pixel, vec2<i32>(1, 0), true), 1.0));
+}
+
+@compute @workgroup_size(8, 8)
+fn blur_y(@builtin(global_invocation_id) id: vec3<u32>) {
+    let dim = vec2<u32>(textureDimensions(destination));
+    if id.x >= dim.x || id.y >= dim.y {
+        return;
+    }
+    let pixel = vec2<i32>(id.xy);
+    textureStore(destination, pixel, vec4<f32>(blur(pixel, vec2<i32>(0, 1), false), 1.0));
+}
+
+// Gaussian blur of the source along dir
+fn blur(pixel: vec2<i32>, dir: vec2<i32>, extract: bool) -> vec3<f32> {
+    let dim = vec2<i32>(textureDimensions(source));
+    var sum = vec3<f32>(0.0);
+    var weight = 0.0;
+    for (var i = -radius; i <= radius; i++) {
+        let p = clamp(pixel + dir * i, vec2<i32>(0), dim - 1);
+        var color = textureLoad(source, p, 0).rgb;
+        if extract {
+            color = bright(color);
+        }
+        let w = exp(-f32(i * i) / (2.0 * sigma * sigma));
+        sum += color * w;
+        weight += w;
+    }
+    return sum / weight;
+}
+
+// Part of color above the threshold, keeping its hue
+fn bright(color: vec3<f32>) -> vec3<f32> {
+    let luma = dot(color, vec3<f32>(0.2126, 0.7152, 0.0722));
+    return color * max(luma - post.bloom_threshold, 0.0) / max(luma, 0.0001);
+}
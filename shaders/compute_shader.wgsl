@@ -1,12 +1,39 @@
 @group(0) @binding(0) var<storage, read> shapes: array<Shape>;
 @group(0) @binding(1) var<uniform> g: Globals;
 @group(0) @binding(2) var texture: texture_storage_2d<rgba8unorm, write>;
+@group(0) @binding(3) var<storage, read> materials: array<Material>;
+@group(0) @binding(4) var depth_texture: texture_storage_2d<r32float, write>;
+@group(0) @binding(5) var normal_texture: texture_storage_2d<rgba16float, write>;
+@group(0) @binding(6) var<storage, read_write> accumulation: array<vec4<f32>>;
+@group(0) @binding(7) var surface_texture: texture_2d<f32>;
+@group(0) @binding(8) var surface_sampler: sampler;
+@group(0) @binding(9) var environment_texture: texture_2d<f32>;
+@group(0) @binding(10) var environment_sampler: sampler;
+@group(0) @binding(11) var<storage, read> instances: array<vec3<f32>>;
+@group(0) @binding(12) var id_texture: texture_storage_2d<r32uint, write>;
  
 struct Shape {
     pos: vec3<f32>,
     id: u32,
     v1: vec3<f32>,
     f1: f32,
+    v2: vec3<f32>,
+    f2: f32,
+    m1: mat3x3<f32>,
+    material: u32,
+    index: u32,
+};
+
+struct Material {
+    color: vec3<f32>,
+    reflectivity: f32,
+    emissive: vec3<f32>,
+    alpha: f32,
+    ior: f32,
+    texture_scale: f32,
+    translucency: f32,
+    specular: f32,
+    roughness: f32,
 };
 
 struct Globals {
@@ -17,106 +44,599 @@ struct Globals {
     focal_length: f32,
     time: f32,
     shape_amount: u32,
+    max_steps: u32,
+    max_dist: f32,
+    surface_dist: f32,
+    max_bounces: u32,
+    gamma_correction: u32,
+    light_dir: vec3<f32>,
+    light_type: u32,
+    light_color: vec3<f32>,
+    debug_mode: u32,
+    checker_enabled: u32,
+    checker_scale: f32,
+    checker_color_a: vec3<f32>,
+    checker_color_b: vec3<f32>,
+    fog_color: vec3<f32>,
+    fog_density: f32,
+    aa_samples: u32,
+    grid_enabled: u32,
+    grid_spacing: f32,
+    grid_color: vec3<f32>,
+    tonemap: u32,
+    exposure: f32,
+    vignette_strength: f32,
+    vignette_radius: f32,
+    frame_index: u32,
+    normal_epsilon: f32,
+    rim_color: vec3<f32>,
+    rim_power: f32,
+    max_internal_reflections: u32,
+    shadow_bias: f32,
+    environment_enabled: u32,
+    seed: u32,
+    ambient_color: vec3<f32>,
 };
 
-const max_steps: u32 = 100u;
-const max_dist: f32 = 50.0;
-const surface_dist: f32 = 0.0001;
-const epsilon: f32 = 0.00001; // surface_dist * 0.1
 const shadow_step: f32 = 0.005; // surface_dist * 50
-const soft_shadow_sharpness: f32 = 8.0;
-const specular_sharpness: f32 = 10.0;
-const specular_intensity: f32 = 0.3;
+const soft_shadow_sharpness: f32 = 8.0; // Mirrored by SOFT_SHADOW_SHARPNESS
 const diffuse_intensity: f32 = 0.7;
 const occlusion_intensity: f32 = 1.0;
 const occlusion_init_step = 0.01;
 const occlusion_step_scale = 0.01;
 const occlusion_weight_drop = 0.85;
-const ambient_intensity: f32 = 0.05;
+const pi: f32 = 3.14159265;
+const subsurface_depth: f32 = 0.5;
+const subsurface_samples: i32 = 5;
+const subsurface_wrap: f32 = 0.25;
+const subsurface_sharpness: f32 = 4.0;
 const back_intensity: f32 = 0.05;
 const fresnel_intensity: f32 = 0.15;
 const fog_inesity: f32 = 2.0;
 
+// Bounds how deep shapes can be nested, must match MAX_SHAPE_DEPTH
 const stack_size: u32 = 10u;
 
+// Written to the id texture where no shape is hit
+const missed_id: u32 = 0xffffffffu;
+
+const point_light: u32 = 0u;
+const directional_light: u32 = 1u;
+
+// Replaced with RunConfig::workgroup_size when the pipeline is created
 @compute @workgroup_size(1)
 fn cs_main(@builtin(global_invocation_id) coord: vec3<u32>) {
+    // Workgroups on the right and bottom edges can extend past the screen
+    if coord.x >= g.screen_dim.x || coord.y >= g.screen_dim.y {
+        return;
+    }
+
+    // Samples are spread over an n x n grid within the pixel
+    let n = u32(round(sqrt(f32(g.aa_samples))));
+    var color = vec3<f32>(0.0);
+    var depth = g.max_dist;
+    // Normal and leaf of the nearest hit, zero and -1 on a miss
+    var surface_normal = vec4<f32>(0.0);
+    var leaf = -1;
+    for (var sy = 0u; sy < n; sy++) {
+        for (var sx = 0u; sx < n; sx++) {
+            // Single samples keep the pixel corner for stable output unless accumulating
+            // Each accumulated frame gets new jitter
+            let sample_index = sx + sy * n + g.frame_index * n * n;
+            let jitter = (vec2<f32>(f32(sx), f32(sy)) + hash2(coord.xy, sample_index)) / f32(n);
+            let offset = select(jitter, vec2<f32>(0.0), n == 1u && g.frame_index == 0u);
+            let p = vec2<f32>(coord.xy) + offset;
+            let sample = sample_color(p);
+            color += sample.color.rgb;
+            // Nearest hit of all samples
+            if sample.color.a < depth {
+                depth = sample.color.a;
+                surface_normal = sample.normal;
+                leaf = sample.leaf;
+            }
+        }
+    }
+    color /= f32(n * n);
+
+    // Running average over the frames since the last reset
+    if g.frame_index > 0u {
+        let index = coord.y * g.screen_dim.x + coord.x;
+        if g.frame_index > 1u {
+            color = mix(accumulation[index].rgb, color, 1.0 / f32(g.frame_index));
+        }
+        accumulation[index] = vec4<f32>(color, 1.0);
+    }
+
+    // Shape index of the nearest hit, missed_id on a miss
+    var shape_id = missed_id;
+    if depth < g.max_dist && leaf >= 0 {
+        shape_id = shapes[leaf].index;
+    }
 
+    // Debug output is written as is
+    // Shading is done in linear space, the texture holds srgb encoded colors
+    if g.debug_mode == 0u || g.debug_mode == 3u {
+        color = tonemap(color * g.exposure);
+        color *= vignette(vec2<f32>(coord.xy));
+        if g.gamma_correction != 0u {
+            color = linear_to_srgb(color);
+        }
+    }
+
+    textureStore(texture, coord.xy, vec4<f32>(color, 1.0));
+    textureStore(depth_texture, coord.xy, vec4<f32>(depth, 0.0, 0.0, 0.0));
+    textureStore(normal_texture, coord.xy, surface_normal);
+    textureStore(id_texture, coord.xy, vec4<u32>(shape_id, 0u, 0u, 0u));
+}
+
+struct Sample {
+    // Color in rgb, distance to the first hit in a, max_dist on a miss
+    color: vec4<f32>,
+    // Normal at the first hit with w = 1, zero on a miss
+    normal: vec4<f32>,
+    // Index of the leaf shape at the first hit, -1 if none
+    leaf: i32,
+}
+
+// Color of the ray through pixel position p
+fn sample_color(p: vec2<f32>) -> Sample {
+    let ro = g.camera_pos; // + vec3<f32>(g.time, 0.0, 0.0);
+    let rd = ray_dir(p);
+    var sample: Sample;
+    switch g.debug_mode {
+        // Normals
+        case 1u: {
+            sample = normal_color(ro, rd);
+        }
+        // Step count
+        case 2u: {
+            sample = step_color(ro, rd);
+        }
+        default: {
+            sample = raymarch_color(ro, rd);
+        }
+    }
+    let depth = sample.color.a;
+    if g.grid_enabled != 0u {
+        sample.color = vec4<f32>(grid_overlay(ro, rd, sample.color.rgb, depth), depth);
+    }
+    // Bounds
+    if g.debug_mode == 3u {
+        sample.color = vec4<f32>(bounds_overlay(ro, rd, sample.color.rgb, depth), depth);
+    }
+    return sample;
+}
+
+// Normal at pos with w = 1 if it was hit at dist, zero on a miss
+fn hit_normal(pos: vec3<f32>, dist: f32) -> vec4<f32> {
+    if dist >= g.max_dist {
+        return vec4<f32>(0.0);
+    }
+    return vec4<f32>(normal(pos), 1.0);
+}
+
+// Mirrors ShapeGPU::is_leaf
+fn is_leaf(id: u32) -> bool {
+    return (id >= 6u && id <= 8u) || id == 15u || id == 16u || (id >= 18u && id <= 21u);
+}
+
+// Draws the edges of leaf bounding boxes over color
+// m1: bounds of leaves as min and max columns, zero if unbounded
+// Edges further away than depth are hidden behind geometry
+fn bounds_overlay(ro: vec3<f32>, rd: vec3<f32>, color: vec3<f32>, depth: f32) -> vec3<f32> {
+    var line = 0.0;
+    for (var i = 0u; i < g.shape_amount; i++) {
+        let shape = shapes[i];
+        let bmin = shape.m1[0];
+        let bmax = shape.m1[1];
+        if !is_leaf(shape.id) || all(bmin == bmax) {
+            continue;
+        }
+        // Slab test, both the front and back faces have visible edges
+        let t1 = (bmin - ro) / rd;
+        let t2 = (bmax - ro) / rd;
+        let t_near = max(max(min(t1.x, t2.x), min(t1.y, t2.y)), min(t1.z, t2.z));
+        let t_far = min(min(max(t1.x, t2.x), max(t1.y, t2.y)), max(t1.z, t2.z));
+        if t_near > t_far {
+            continue;
+        }
+        line = max(line, box_edge(ro, rd, t_near, depth, bmin, bmax));
+        line = max(line, box_edge(ro, rd, t_far, depth, bmin, bmax));
+    }
+    return mix(color, vec3<f32>(1.0, 0.8, 0.0), line);
+}
+
+// Coverage of a box edge at distance t along the ray
+fn box_edge(ro: vec3<f32>, rd: vec3<f32>, t: f32, depth: f32, bmin: vec3<f32>, bmax: vec3<f32>) -> f32 {
+    if t <= 0.0 || t >= depth {
+        return 0.0;
+    }
+    let p = ro + rd * t;
+    let d = max(min(p - bmin, bmax - p), vec3<f32>(0.0));
+    // The point lies on a face, so the smallest distance is ~0 and the middle one is to the nearest edge
+    let edge_dist = max(min(d.x, d.y), min(max(d.x, d.y), d.z));
+    // World size of a pixel at distance t
+    let footprint = 2.0 * t / (f32(g.screen_dim.y) * g.focal_length);
+    return 1.0 - clamp(edge_dist / (1.5 * footprint), 0.0, 1.0);
+}
+
+// Direction of the camera ray through pixel position p
+fn ray_dir(p: vec2<f32>) -> vec3<f32> {
     // Left handed coordinate system, x right, y up, z in
     let uv = vec2<f32>(
-        f32(coord.x) / f32(g.screen_dim.x) * 2.0 - 1.0,
-        (1.0 - f32(coord.y) / f32(g.screen_dim.y)) * 2.0 - 1.0
+        p.x / f32(g.screen_dim.x) * 2.0 - 1.0,
+        (1.0 - p.y / f32(g.screen_dim.y)) * 2.0 - 1.0
     );
+    return normalize(g.camera_rot * vec3<f32>(uv.xy, g.focal_length));
+}
 
-    let ro = g.camera_pos; // + vec3<f32>(g.time, 0.0, 0.0);
-    let rd = normalize(g.camera_rot * vec3<f32>(uv.xy, g.focal_length));
-    let color = raymarch_color(ro, rd);
+// Blends grid lines on the XZ plane at y = 0 over color
+// Lines further away than depth are hidden behind geometry
+fn grid_overlay(ro: vec3<f32>, rd: vec3<f32>, color: vec3<f32>, depth: f32) -> vec3<f32> {
+    let t = -ro.y / rd.y;
+    if rd.y == 0.0 || t <= 0.0 || t >= depth {
+        return color;
+    }
+    let cell = (ro.xz + rd.xz * t) / g.grid_spacing;
+    // World size of a pixel projected onto the plane, replaces fwidth in compute
+    let footprint = 2.0 * t / (f32(g.screen_dim.y) * g.focal_length * abs(rd.y));
+    let width = max(footprint / g.grid_spacing, 0.0001);
+    let line_dist = abs(fract(cell - 0.5) - 0.5) / width;
+    let line = 1.0 - clamp(min(line_dist.x, line_dist.y), 0.0, 1.0);
+    // Fades out towards max distance where lines alias
+    let fade = 1.0 - clamp(t / g.max_dist, 0.0, 1.0);
+    return mix(color, g.grid_color, line * fade);
+}
 
-    textureStore(texture, coord.xy, vec4<f32>(color, 1.0));
+// Pseudo random offset in [0, 1) for a sample of a pixel
+// Integer hashing so the result only depends on the inputs and seed, not on float precision
+fn hash2(pixel: vec2<u32>, sample_index: u32) -> vec2<f32> {
+    let h1 = pcg_hash(pixel.x + pcg_hash(pixel.y + pcg_hash(sample_index + pcg_hash(g.seed))));
+    let h2 = pcg_hash(h1);
+    // Top 24 bits fit exactly in a f32
+    return vec2<f32>(f32(h1 >> 8u), f32(h2 >> 8u)) / 16777216.0;
+}
+
+// PCG permutation of a u32
+fn pcg_hash(v: u32) -> u32 {
+    let state = v * 747796405u + 2891336453u;
+    let word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+    return (word >> 22u) ^ word;
+}
+
+// Maps linear colors above 1 into [0, 1]
+fn tonemap(color: vec3<f32>) -> vec3<f32> {
+    switch g.tonemap {
+        // Reinhard
+        case 1u: {
+            return color / (1.0 + color);
+        }
+        // Narkowicz ACES filmic fit
+        case 2u: {
+            let c = color * 0.6;
+            return clamp((c * (2.51 * c + 0.03)) / (c * (2.43 * c + 0.59) + 0.14), vec3<f32>(0.0), vec3<f32>(1.0));
+        }
+        default: {
+            return color;
+        }
+    }
+}
+
+// Darkening factor at pixel p, d is 1 at the screen edges
+// Fades in between radius and radius + 0.5
+fn vignette(p: vec2<f32>) -> f32 {
+    let d = length(p / vec2<f32>(g.screen_dim) - 0.5) * 2.0;
+    return 1.0 - g.vignette_strength * smoothstep(g.vignette_radius, g.vignette_radius + 0.5, d);
+}
+
+fn linear_to_srgb(color: vec3<f32>) -> vec3<f32> {
+    let c = clamp(color, vec3<f32>(0.0), vec3<f32>(1.0));
+    let low = c * 12.92;
+    let high = 1.055 * pow(c, vec3<f32>(1.0 / 2.4)) - 0.055;
+    return select(high, low, c <= vec3<f32>(0.0031308));
+}
+
+struct MarchResult {
+    dist: f32,
+    steps: u32,
+    leaf: i32, // index of the leaf shape closest to the last position
 }
 
 fn raymarch(ro: vec3<f32>, rd: vec3<f32>) -> f32 {
+    return march(ro, rd).dist;
+}
+
+fn march(ro: vec3<f32>, rd: vec3<f32>) -> MarchResult {
     var t = 0.0;
+    var steps = 0u;
+    var leaf = -1;
 
-    for (var i = 0u; i < max_steps; i++) {
+    for (var i = 0u; i < g.max_steps; i++) {
         let pos = ro + rd * t;
-        let dist = map(pos);
+        let hit = map_hit(pos);
+        let dist = hit.dist;
+        leaf = hit.leaf;
 
         t += dist;
+        steps++;
+
+        if dist < g.surface_dist {
+            break;
+        }
+        if t > g.max_dist {
+            break;
+        }
+    }
+    return MarchResult(t, steps, leaf);
+}
+
+// Marches the ray and up to max_bounces reflections
+// Reflected color is mixed in by the reflectivity of the surface
+// The normal of the first hit is kept for the normal texture
+fn raymarch_color(ro: vec3<f32>, rd: vec3<f32>) -> Sample {
+    var color = vec3<f32>(0.0);
+    var depth = g.max_dist;
+    var first_normal = vec4<f32>(0.0);
+    var first_leaf = -1;
+    var weight = 1.0;
+    var origin = ro;
+    var dir = rd;
+
+    for (var bounce = 0u; bounce <= g.max_bounces; bounce++) {
+        let result = march(origin, dir);
+        let dist = result.dist;
+        if dist >= g.max_dist {
+            color += weight * miss(dir);
+            break;
+        }
+        if bounce == 0u {
+            depth = dist;
+        }
 
-        if dist < surface_dist {
+        let pos = origin + dir * dist;
+        // The march finds no leaf when no shape is visible
+        let leaf = result.leaf;
+        if leaf < 0 {
+            color += weight * miss(dir);
             break;
         }
-        if t > max_dist {
+        let shape = shapes[leaf];
+        let material = materials[shape.material];
+        let normal = normal(pos);
+        if bounce == 0u {
+            first_normal = vec4<f32>(normal, 1.0);
+            first_leaf = leaf;
+        }
+        var local = shade(pos, dir, dist, normal, shape);
+        if material.alpha < 1.0 {
+            local = mix(behind_color(pos, dir, normal, material.ior), local, material.alpha);
+        }
+
+        // Fog covers both the surface and what it reflects
+        let fog = fog_amount(dist);
+        color += weight * mix(local, g.fog_color, fog);
+
+        // Skip second march for non reflective surfaces
+        if material.reflectivity == 0.0 {
             break;
         }
+        weight *= material.reflectivity * (1.0 - fog);
+        origin = pos + normal * shadow_step;
+        dir = reflect(dir, normal);
+    }
+
+    return Sample(vec4<f32>(color, depth), first_normal, first_leaf);
+}
+
+// Color of the surface of shape at pos, excluding reflections
+fn shade(pos: vec3<f32>, dir: vec3<f32>, dist: f32, normal: vec3<f32>, shape: Shape) -> vec3<f32> {
+    let material = materials[shape.material];
+    var surface_color = material.color;
+    // Planes
+    if g.checker_enabled != 0u && shape.id == 8u {
+        surface_color = checker_color(pos, dist);
+    }
+    if material.texture_scale > 0.0 {
+        surface_color *= triplanar(pos - shape.pos, normal, material.texture_scale);
+    }
+    var lit = hit(pos, dir, normal, surface_color, material);
+    if material.translucency > 0.0 {
+        lit += material.translucency * subsurface(pos, dir, normal) * surface_color * g.light_color;
+    }
+    // Emission bypasses lighting so shadows and occlusion do not darken it
+    return (1.0 - material.reflectivity) * lit + material.emissive;
+}
+
+// Color of the first surface behind the transparent surface at pos
+// The ray is bent by ior when entering and leaving the shape
+// The surface behind is shaded as opaque and without reflections
+fn behind_color(pos: vec3<f32>, dir: vec3<f32>, surface_normal: vec3<f32>, ior: f32) -> vec3<f32> {
+    var d = dir;
+    if ior != 1.0 {
+        d = refract(dir, surface_normal, 1.0 / ior);
+    }
+    var exit = pos + d * march_exit(pos, d);
+    if ior != 1.0 {
+        for (var i = 0u; i <= g.max_internal_reflections; i++) {
+            let inward = -normal(exit);
+            let out = refract(d, inward, ior);
+            // Total internal reflection
+            if all(out == vec3<f32>(0.0)) && i < g.max_internal_reflections {
+                d = reflect(d, inward);
+                exit += d * march_exit(exit, d);
+                continue;
+            }
+            if any(out != vec3<f32>(0.0)) {
+                d = out;
+            }
+            break;
+        }
+    }
+    let origin = exit + d * shadow_step;
+    let dist = raymarch(origin, d);
+    if dist >= g.max_dist {
+        return miss(d);
+    }
+    let behind = origin + d * dist;
+    // map_hit finds no leaf when no shape is visible
+    let leaf = map_hit(behind).leaf;
+    if leaf < 0 {
+        return miss(d);
+    }
+    let shape = shapes[leaf];
+    let color = shade(behind, d, dist, normal(behind), shape);
+    return mix(color, g.fog_color, fog_amount(dist));
+}
+
+// Distance along dir until leaving the shapes containing pos
+// Opaque shapes nested inside a transparent one are passed through
+fn march_exit(pos: vec3<f32>, dir: vec3<f32>) -> f32 {
+    var t = shadow_step;
+    for (var i = 0u; i < g.max_steps; i++) {
+        let dist = -map(pos + dir * t);
+        if dist < g.surface_dist || t > g.max_dist {
+            break;
+        }
+        t += dist;
     }
     return t;
 }
 
-fn raymarch_color(ro: vec3<f32>, rd: vec3<f32>) -> vec3<f32> {
-    let dist = raymarch(ro, rd);
-    if dist < max_dist {
-        let pos = ro + rd * dist;
-        //return vec3(1.0);
-        return hit(pos, rd);
-    } else {
-        return miss();
+// Checker pattern from the world xz coordinates
+// Fades to the average color with distance to hide shimmering at grazing angles
+// Texture projected along each axis, blended by how much the normal faces that axis
+// Relative to the shape position so the texture moves with the shape
+fn triplanar(p: vec3<f32>, normal: vec3<f32>, scale: f32) -> vec3<f32> {
+    var weights = pow(abs(normal), vec3(4.0));
+    weights /= weights.x + weights.y + weights.z;
+    let uv = p * scale;
+    // Compute shaders have no derivatives so the base level is always sampled
+    let x = textureSampleLevel(surface_texture, surface_sampler, uv.zy, 0.0).rgb;
+    let y = textureSampleLevel(surface_texture, surface_sampler, uv.xz, 0.0).rgb;
+    let z = textureSampleLevel(surface_texture, surface_sampler, uv.xy, 0.0).rgb;
+    return x * weights.x + y * weights.y + z * weights.z;
+}
+
+fn checker_color(pos: vec3<f32>, dist: f32) -> vec3<f32> {
+    let cell = floor(pos.xz / g.checker_scale);
+    let checker = select(g.checker_color_a, g.checker_color_b, fract(0.5 * (cell.x + cell.y)) > 0.25);
+    let average = 0.5 * (g.checker_color_a + g.checker_color_b);
+    return mix(checker, average, clamp(dist / g.max_dist, 0.0, 1.0));
+}
+
+// Surface normal of the first hit mapped to [0, 1]
+fn normal_color(ro: vec3<f32>, rd: vec3<f32>) -> Sample {
+    let result = march(ro, rd);
+    let dist = result.dist;
+    if dist >= g.max_dist {
+        return Sample(vec4<f32>(miss(rd), g.max_dist), vec4<f32>(0.0), -1);
     }
+    let normal = normal(ro + rd * dist);
+    return Sample(vec4<f32>(0.5 * normal + 0.5, dist), vec4<f32>(normal, 1.0), result.leaf);
 }
 
-fn hit(pos: vec3<f32>, rd: vec3<f32>) -> vec3<f32> {
-    let normal = normal(pos);
-    let light_dir = normalize(g.light_pos - pos);
-    let reflected_dir = normalize(reflect(-light_dir, normal));
+// Amount of march steps relative to max_steps, blue for few and red for many
+fn step_color(ro: vec3<f32>, rd: vec3<f32>) -> Sample {
+    let result = march(ro, rd);
+    let t = f32(result.steps) / f32(g.max_steps);
+    let red = clamp(2.0 * t - 1.0, 0.0, 1.0);
+    let blue = clamp(1.0 - 2.0 * t, 0.0, 1.0);
+    let dist = min(result.dist, g.max_dist);
+    let color = vec4<f32>(red, 1.0 - red - blue, blue, dist);
+    return Sample(color, hit_normal(ro + rd * dist, dist), result.leaf);
+}
+
+// Direction from pos towards the light
+fn to_light(pos: vec3<f32>) -> vec3<f32> {
+    if g.light_type == directional_light {
+        return -g.light_dir;
+    }
+    return normalize(g.light_pos - pos);
+}
+
+// Directional lights are infinitely far away
+fn light_distance(pos: vec3<f32>) -> f32 {
+    if g.light_type == directional_light {
+        return g.max_dist;
+    }
+    return length(g.light_pos - pos);
+}
+
+fn hit(pos: vec3<f32>, rd: vec3<f32>, normal: vec3<f32>, surface_color: vec3<f32>, material: Material) -> vec3<f32> {
+    let light_dir = to_light(pos);
     let view_dir = normalize(-rd);
+    let half_dir = normalize(light_dir + view_dir);
 
-    let ambient = ambient_intensity;
-    let specular = specular_intensity * pow(clamp(dot(reflected_dir, view_dir), 0.0, 1.0), specular_sharpness);
+    let ambient = g.ambient_color;
+    let specular = material.specular * pow(clamp(dot(normal, half_dir), 0.0, 1.0), shininess(material.roughness));
     let diffuse = diffuse_intensity * clamp(dot(light_dir, normal), 0.0, 1.0);
     let fresnel = fresnel_intensity * pow(1.0 + dot(rd, normal), 5.0);
     let back = back_intensity * clamp(dot(normal, -light_dir), 0.0, 1.0);
 
-    let shadow = soft_shadow(pos, soft_shadow_sharpness);
+    let shadow = soft_shadow(pos, normal, soft_shadow_sharpness);
     let occlusion = ambient_occlusion(pos, normal);
 
-    let fog = 1.0 - length(g.camera_pos - pos) / max_dist;
+    let fog = 1.0 - length(g.camera_pos - pos) / g.max_dist;
 
-    var color = vec3<f32>(0.0, 1.0, 1.0);
+    var color = surface_color;
 
-    let light = (ambient + back + fresnel) * occlusion + (diffuse + specular * occlusion) * shadow;
+    let light = (ambient + back + fresnel) * occlusion + (diffuse * g.light_color + specular * occlusion) * shadow;
     color *= light * fog;
 
-    // Gamma correction
-    color = pow(color, vec3<f32>(0.4545));
+    // Rim is added on top so it stays visible in shadow
+    let rim = pow(1.0 - clamp(dot(normal, view_dir), 0.0, 1.0), g.rim_power);
+    color += rim * g.rim_color;
 
     return color;
 }
 
-fn miss() -> vec3<f32> {
+// Light scattered through the shape towards the viewer, strongest when backlit and thin
+// Thickness is estimated by sampling the distance inside the shape below the surface
+// Not shadowed since the light is assumed to pass through the shape
+fn subsurface(pos: vec3<f32>, rd: vec3<f32>, normal: vec3<f32>) -> f32 {
+    var thickness = 0.0;
+    for (var i = 1; i <= subsurface_samples; i++) {
+        let depth = subsurface_depth * f32(i) / f32(subsurface_samples);
+        // Deep inside the distance approaches the depth, near the far side it drops to 0
+        thickness += clamp(-map(pos - normal * depth) / depth, 0.0, 1.0);
+    }
+    let transmission = 1.0 - thickness / f32(subsurface_samples);
+    let back = pow(clamp(dot(rd, to_light(pos)), 0.0, 1.0), subsurface_sharpness);
+    return (subsurface_wrap + back) * transmission;
+}
+
+// Blinn-Phong exponent of a roughness in [0, 1], using the common alpha = roughness^2 mapping
+// Clamped so smooth surfaces keep a visible highlight instead of an infinitely small one
+fn shininess(roughness: f32) -> f32 {
+    let alpha = max(roughness * roughness, 0.01);
+    return min(2.0 / (alpha * alpha) - 2.0, 4096.0);
+}
+
+// Background in direction dir
+// The environment if set, otherwise matches the fog color when fog is enabled
+fn miss(dir: vec3<f32>) -> vec3<f32> {
+    if g.environment_enabled != 0u {
+        return environment(dir);
+    }
+    if g.fog_density > 0.0 {
+        return g.fog_color;
+    }
     return vec3<f32>(0.0, 0.0, 0.0);
 }
 
+// Equirectangular lookup, u goes around the y axis and v from straight up to straight down
+fn environment(dir: vec3<f32>) -> vec3<f32> {
+    let d = normalize(dir);
+    let u = atan2(d.x, d.z) / (2.0 * pi) + 0.5;
+    let v = acos(clamp(d.y, -1.0, 1.0)) / pi;
+    return textureSampleLevel(environment_texture, environment_sampler, vec2(u, v), 0.0).rgb;
+}
+
+// Amount of fog in [0, 1] after dist along a ray
+fn fog_amount(dist: f32) -> f32 {
+    return 1.0 - exp(-g.fog_density * dist);
+}
+
 // TODO check parameters 0.01, 0.01 and steps
 fn ambient_occlusion(pos: vec3<f32>, normal: vec3<f32>) -> f32 {
     var occlusion = 0.0;
@@ -131,7 +651,7 @@ fn ambient_occlusion(pos: vec3<f32>, normal: vec3<f32>) -> f32 {
 }
 
 fn normal(pos: vec3<f32>) -> vec3<f32> {
-    let e = vec2<f32>(epsilon, 0.0);
+    let e = vec2<f32>(g.normal_epsilon, 0.0);
     let center = map(pos);
     let diff = vec3<f32>(
         map(pos + e.xyy) - center,
@@ -141,10 +661,11 @@ fn normal(pos: vec3<f32>) -> vec3<f32> {
     return normalize(diff);
 }
 
-fn hard_shadow(pos: vec3<f32>) -> f32 {
-    let light_dir = normalize(g.light_pos - pos);
-    let light_dist = length(g.light_pos - pos);
-    let start_pos = pos + light_dir * shadow_step;
+// Shadow rays start shadow_bias along the normal to not hit the surface itself
+fn hard_shadow(pos: vec3<f32>, normal: vec3<f32>) -> f32 {
+    let light_dir = to_light(pos);
+    let light_dist = light_distance(pos);
+    let start_pos = pos + normal * g.shadow_bias;
 
     let dist = raymarch(start_pos, light_dir);
     if dist < light_dist {
@@ -154,27 +675,28 @@ fn hard_shadow(pos: vec3<f32>) -> f32 {
     }
 }
 
-fn soft_shadow(pos: vec3<f32>, k: f32) -> f32 {
-    let light_dir = normalize(g.light_pos - pos);
-    let light_dist = length(g.light_pos - pos);
+fn soft_shadow(pos: vec3<f32>, normal: vec3<f32>, k: f32) -> f32 {
+    let light_dir = to_light(pos);
+    let light_dist = light_distance(pos);
+    let start_pos = pos + normal * g.shadow_bias;
 
     var shadow = 1.0;
     var ph = 1e20;
-    var t = shadow_step;
-    for (var i = 0u; i < max_steps; i++) {
-        let pos = pos + light_dir * t;
+    var t = 0.0;
+    for (var i = 0u; i < g.max_steps; i++) {
+        let pos = start_pos + light_dir * t;
         let dist = map(pos);
 
         let y = dist * dist / (2.0 * ph);
         let d = sqrt(dist * dist - y * y);
-        shadow = min(shadow, k * d / max(0.0, t - y));
+        shadow = min(shadow, k * d / max(1e-6, t - y));
         ph = dist;
         t += dist;
 
         if t >= light_dist {
             break;
         }
-        if dist < surface_dist {
+        if dist < g.surface_dist {
             break;
         }
     }
@@ -184,17 +706,28 @@ fn soft_shadow(pos: vec3<f32>, k: f32) -> f32 {
 }
 
 struct SE {
-    op_type: i32, // 0 un, 1 in, 2, sub, 3 sun, 4 sin, 5 ssub
+    op_type: u32, // id of the operation, 0 un, 1 in, 2 sub, 9 rep, 10 rep lim, 11 rot, 12 scl
     op_amount: i32,
     dist: f32,
+    pos: vec3<f32>, // sample position for the operands
+    param: f32, // operation parameter applied to the result
+    leaf: i32, // index of the leaf shape closest to the surface
 }
 
+struct MapResult {
+    dist: f32,
+    leaf: i32, // index of the leaf shape which determined dist
+}
 
-// If wgsl supports switching on const, use that instead
 fn map(pos: vec3<f32>) -> f32 {
-    var stack = array<SE, 10>();
+    return map_hit(pos).dist;
+}
+
+// If wgsl supports switching on const, use that instead
+fn map_hit(pos: vec3<f32>) -> MapResult {
+    var stack = array<SE, stack_size>();
     var si = 0; // stack index
-    stack[si] = SE(0, i32(g.shape_amount), max_dist);
+    stack[si] = SE(0u, i32(g.shape_amount), g.max_dist, pos, 0.0, -1);
     var i = 0;
 
     while true {
@@ -205,46 +738,193 @@ fn map(pos: vec3<f32>) -> f32 {
                 break;
             } else {
                 si--;
-                stack[si].dist = min(stack[si].dist, stack[si + 1].dist);
+                stack[si] = combine(stack[si], op_result(stack[si + 1]), stack[si + 1].leaf);
                 continue;
             }
         }
         stack[si].op_amount--;
 
-        switch shapes[i].id {
+        let shape = shapes[i];
+        let p = stack[si].pos;
+        switch shape.id {
             // Push union to stack
             case 0u: {
                 si++;
-                stack[si] = SE(0, 2, max_dist);
+                stack[si] = SE(0u, 2, g.max_dist, p, 0.0, -1);
             }
             // Push intersection to stack
             case 1u: {
                 si++;
-                stack[si] = SE(1, 2, -1.0);
+                stack[si] = SE(1u, 2, -g.max_dist, p, 0.0, -1);
+            }
+            // Push subtraction to stack
+            case 2u: {
+                si++;
+                stack[si] = SE(2u, 2, g.max_dist, p, 0.0, -1);
+            }
+            // Push repetition to stack
+            // pos: center
+            case 9u: {
+                si++;
+                stack[si] = SE(9u, 1, g.max_dist, op_repeat(p - shape.pos, shape.v1) + shape.pos, 0.0, -1);
+            }
+            // Push limited repetition to stack
+            // pos: center
+            case 10u: {
+                si++;
+                stack[si] = SE(10u, 1, g.max_dist, op_repeat_limited(p - shape.pos, shape.v1, shape.v2) + shape.pos, 0.0, -1);
+            }
+            // Push rotation to stack
+            // m1: inverse rotation
+            case 11u: {
+                si++;
+                stack[si] = SE(11u, 1, g.max_dist, shape.m1 * p, 0.0, -1);
+            }
+            // Push rotation around a center to stack
+            // v1: center, m1: inverse rotation
+            case 23u: {
+                si++;
+                stack[si] = SE(23u, 1, g.max_dist, shape.m1 * (p - shape.v1) + shape.v1, 0.0, -1);
+            }
+            // Push scale to stack
+            // f1: factor
+            case 12u: {
+                si++;
+                stack[si] = SE(12u, 1, g.max_dist, p / shape.f1, shape.f1, -1);
+            }
+            // Push bend to stack
+            // pos: center, f1: amount
+            case 13u: {
+                si++;
+                stack[si] = SE(13u, 1, g.max_dist, op_bend(p - shape.pos, shape.f1) + shape.pos, shape.f1, -1);
+            }
+            // Push onion to stack
+            // f1: thickness
+            case 14u: {
+                si++;
+                stack[si] = SE(14u, 1, g.max_dist, p, shape.f1, -1);
+            }
+            // Push visibility to stack
+            // f1: 1 if visible, 0 if hidden
+            case 17u: {
+                si++;
+                stack[si] = SE(17u, 1, g.max_dist, p, shape.f1, -1);
+            }
+            // Push orbit to stack
+            // v1: center, f1: radius, f2: speed
+            case 22u: {
+                si++;
+                let angle = g.time * shape.f2;
+                let offset = shape.v1 + shape.f1 * vec3(cos(angle), 0.0, sin(angle));
+                stack[si] = SE(22u, 1, g.max_dist, p - offset, 0.0, -1);
+            }
+            // Combine all instances of the following leaf as a single operand
+            // f1: first offset, f2: amount of offsets, both bitcast from u32
+            case 24u: {
+                i++;
+                let start = bitcast<u32>(shape.f1);
+                let end = min(start + bitcast<u32>(shape.f2), arrayLength(&instances));
+                var dist = g.max_dist;
+                for (var j = start; j < end; j++) {
+                    dist = min(dist, shape_dist(p - instances[j], i));
+                }
+                stack[si] = combine(stack[si], dist, i);
             }
-            // case 2u: {
-            //     si++;
-            //     stack[si] = SE(2, 2, -1.0);
-            // }
             // Perform current operation on stack
             default: {
-                switch stack[si].op_type {
-                    // Union
-                    case 0: {
-                        stack[si].dist = min(stack[si].dist, shape_dist(pos, i));
-                    }
-                    // Intersection
-                    case 1: {
-                        stack[si].dist = max(stack[si].dist, shape_dist(pos, i));
-                    }
-                    default: {}
-                }
+                stack[si] = combine(stack[si], shape_dist(p, i), i);
             }
         }
 
         i++;
     }
-    return stack[si].dist;
+    return MapResult(stack[si].dist, stack[si].leaf);
+}
+
+// Applies the final step of an operation to its result
+fn op_result(se: SE) -> f32 {
+    switch se.op_type {
+        // Scale, distance is scaled back to keep the field a valid distance
+        case 12u: {
+            return se.dist * se.param;
+        }
+        // Bend, distance is scaled down since bending does not preserve distances
+        // Large amounts can still overstep and cause artifacts
+        case 13u: {
+            return se.dist / (1.0 + abs(se.param));
+        }
+        // Onion, shell around the surface of the operand
+        case 14u: {
+            return abs(se.dist) - se.param;
+        }
+        // Visibility, hidden shapes are never hit
+        case 17u: {
+            return select(g.max_dist, se.dist, se.param != 0.0);
+        }
+        default: {
+            return se.dist;
+        }
+    }
+}
+
+// Combines the distance of an operand with the current result of the operation
+// Keeps track of which leaf determined the result
+fn combine(se: SE, dist: f32, leaf: i32) -> SE {
+    var result = se;
+    switch se.op_type {
+        // Union
+        case 0u: {
+            if dist < se.dist {
+                result.dist = dist;
+                result.leaf = leaf;
+            }
+        }
+        // Intersection
+        case 1u: {
+            if dist > se.dist {
+                result.dist = dist;
+                result.leaf = leaf;
+            }
+        }
+        // Subtraction, first operand is the base shape
+        case 2u: {
+            if se.op_amount == 1 || -dist > se.dist {
+                result.dist = select(-dist, dist, se.op_amount == 1);
+                result.leaf = leaf;
+            }
+        }
+        // Single operand operations
+        default: {
+            result.dist = dist;
+            result.leaf = leaf;
+        }
+    }
+    return result;
+}
+
+// v1: spacing, 0 disables repetition on that axis
+fn op_repeat(pos: vec3<f32>, spacing: vec3<f32>) -> vec3<f32> {
+    let disabled = spacing == vec3<f32>(0.0);
+    let s = select(spacing, vec3<f32>(1.0), disabled);
+    let q = pos + 0.5 * s;
+    let repeated = q - s * floor(q / s) - 0.5 * s;
+    return select(repeated, pos, disabled);
+}
+
+// v1: spacing, 0 disables repetition on that axis
+// v2: count, cell index is clamped to [-count, count]
+fn op_repeat_limited(pos: vec3<f32>, spacing: vec3<f32>, count: vec3<f32>) -> vec3<f32> {
+    let disabled = spacing == vec3<f32>(0.0);
+    let s = select(spacing, vec3<f32>(1.0), disabled);
+    let repeated = pos - s * clamp(round(pos / s), -count, count);
+    return select(repeated, pos, disabled);
+}
+
+// Rotates the xy plane by amount * x
+fn op_bend(pos: vec3<f32>, amount: f32) -> vec3<f32> {
+    let c = cos(amount * pos.x);
+    let s = sin(amount * pos.x);
+    return vec3<f32>(c * pos.x - s * pos.y, s * pos.x + c * pos.y, pos.z);
 }
 
 fn shape_dist(pos: vec3<f32>, i: i32) -> f32 {
@@ -259,8 +939,27 @@ fn shape_dist(pos: vec3<f32>, i: i32) -> f32 {
         case 8u: {
             return plane_sdf(pos, shape);
         }
+        case 15u: {
+            return mandelbulb_sdf(pos, shape);
+        }
+        case 16u: {
+            return disk_sdf(pos, shape);
+        }
+        case 18u: {
+            return menger_sdf(pos, shape);
+        }
+        case 20u: {
+            return pyramid_sdf(pos, shape);
+        }
+        case 21u: {
+            return link_sdf(pos, shape);
+        }
+        // f2: user id as u32 bits
+        case 19u: {
+            return sdf_custom(bitcast<u32>(shape.f2), pos - shape.pos, shape.v1, shape.f1);
+        }
         default: {
-            return max_dist;
+            return g.max_dist;
         }
     }
 }
@@ -280,3 +979,100 @@ fn box_exact_sdf(pos: vec3<f32>, shape: Shape) -> f32 {
 fn plane_sdf(pos: vec3<f32>, shape: Shape) -> f32 {
     return dot((pos - shape.pos), shape.v1);
 }
+
+// v1: normal
+// f1: radius
+fn disk_sdf(pos: vec3<f32>, shape: Shape) -> f32 {
+    let p = pos - shape.pos;
+    let height = dot(p, shape.v1);
+    let radial = length(p - shape.v1 * height);
+    // Outside the rim the closest point is on the edge rather than the face
+    return length(vec2<f32>(max(radial - shape.f1, 0.0), height));
+}
+
+// f1: power
+// f2: iterations
+fn mandelbulb_sdf(pos: vec3<f32>, shape: Shape) -> f32 {
+    let p = pos - shape.pos;
+    let power = shape.f1;
+    var z = p;
+    var dr = 1.0;
+    var r = 0.0;
+    for (var i = 0u; i < u32(shape.f2); i++) {
+        r = length(z);
+        if r > 2.0 {
+            break;
+        }
+        let theta = acos(clamp(z.z / max(r, 1e-6), -1.0, 1.0)) * power;
+        let phi = atan2(z.y, z.x) * power;
+        dr = pow(r, power - 1.0) * power * dr + 1.0;
+        let zr = pow(r, power);
+        z = zr * vec3<f32>(sin(theta) * cos(phi), sin(theta) * sin(phi), cos(theta)) + p;
+    }
+    // Avoid log(0) at the center
+    let rc = max(r, 1e-6);
+    return 0.5 * log(rc) * rc / dr;
+}
+
+// f1: size
+// f2: iterations
+fn menger_sdf(pos: vec3<f32>, shape: Shape) -> f32 {
+    let p = (pos - shape.pos) / shape.f1;
+    // Bounding cube, exact outside so missed rays leave quickly
+    let q = abs(p) - vec3<f32>(1.0);
+    var d = length(max(q, vec3<f32>(0.0))) + min(max(q.x, max(q.y, q.z)), 0.0);
+    var s = 1.0;
+    for (var i = 0u; i < u32(shape.f2); i++) {
+        let m = p * s;
+        let a = m - 2.0 * floor(m / 2.0) - vec3<f32>(1.0);
+        s *= 3.0;
+        let r = abs(vec3<f32>(1.0) - 3.0 * abs(a));
+        let da = max(r.x, r.y);
+        let db = max(r.y, r.z);
+        let dc = max(r.z, r.x);
+        // Cross shaped hole carved at this level
+        let c = (min(da, min(db, dc)) - 1.0) / s;
+        d = max(d, c);
+    }
+    return d * shape.f1;
+}
+
+// f1: base
+// v1.x: height
+fn pyramid_sdf(pos: vec3<f32>, shape: Shape) -> f32 {
+    // Evaluated for a unit wide base and scaled back
+    let p = (pos - shape.pos) / shape.f1;
+    let h = shape.v1.x / shape.f1;
+    // Below the base, or without height, the closest point is on the base square
+    // The face formula below degenerates into a plane without height
+    if p.y <= 0.0 || h <= 1e-6 {
+        let q = abs(p) - vec3<f32>(0.5, 0.0, 0.5);
+        return length(max(q, vec3<f32>(0.0))) * shape.f1;
+    }
+    let m2 = h * h + 0.25;
+    // Fold into a single face
+    var xz = abs(p.xz);
+    if xz.y > xz.x {
+        xz = xz.yx;
+    }
+    xz -= vec2<f32>(0.5);
+    let q = vec3<f32>(xz.y, h * p.y - 0.5 * xz.x, h * xz.x + 0.5 * p.y);
+
+    let s = max(-q.x, 0.0);
+    let t = clamp((q.y - 0.5 * xz.y) / (m2 + 0.25), 0.0, 1.0);
+    let a = m2 * (q.x + s) * (q.x + s) + q.y * q.y;
+    let b = m2 * (q.x + 0.5 * t) * (q.x + 0.5 * t) + (q.y - m2 * t) * (q.y - m2 * t);
+    let d2 = select(min(a, b), 0.0, min(q.y, -q.x * m2 - q.y * 0.5) > 0.0);
+    let d = sqrt((d2 + q.z * q.z) / m2) * sign(max(q.z, -p.y));
+    // Inside, the base may be closer than the faces
+    return max(d, -p.y) * shape.f1;
+}
+
+// v1.x: half length of the straight sides
+// v1.y: inner radius
+// f1: tube radius, clamped on the cpu
+fn link_sdf(pos: vec3<f32>, shape: Shape) -> f32 {
+    let p = pos - shape.pos;
+    let q = vec2<f32>(p.x, max(abs(p.y) - shape.v1.x, 0.0));
+    return length(vec2<f32>(length(q) - shape.v1.y, p.z)) - shape.f1;
+}
@@ -0,0 +1,6 @@
+// Default distance of custom shapes, replaced by RunConfig::custom_sdf
+// id: user id of the shape
+// p: position relative to the shape
+fn sdf_custom(id: u32, p: vec3<f32>, v1: vec3<f32>, f1: f32) -> f32 {
+    return 1e10;
+}
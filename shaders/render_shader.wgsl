@@ -26,8 +26,103 @@ fn vs_main(
 var t_diffuse: texture_2d<f32>;
 @group(0)@binding(1)
 var s_diffuse: sampler;
+@group(0) @binding(2)
+var t_depth: texture_2d<f32>;
+@group(0) @binding(3)
+var<uniform> post: PostGlobals;
+@group(0) @binding(4)
+var t_bloom: texture_2d<f32>;
 
+// Must match PostGlobals in render.rs and the bloom shader
+struct PostGlobals {
+    focus_distance: f32,
+    aperture: f32,
+    bloom_threshold: f32,
+    bloom_intensity: f32,
+    outline_color: vec3<f32>,
+    outline_thickness: f32,
+};
+
+const dof_samples: i32 = 16;
+const max_coc: f32 = 8.0;
+const golden_angle: f32 = 2.39996;
+// Relative depth difference between neighbors above which a pixel is on an outline
+const outline_depth_threshold: f32 = 0.1;
+
+// The texture holds srgb encoded colors
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    return textureSample(t_diffuse, s_diffuse, in.uv);
+    return post_color(in.uv);
+}
+
+// Srgb surfaces encode on write so the color is decoded first
+@fragment
+fn fs_main_srgb(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = post_color(in.uv);
+    return vec4<f32>(srgb_to_linear(color.rgb), color.a);
+}
+
+fn post_color(uv: vec2<f32>) -> vec4<f32> {
+    var color = dof_color(uv);
+    if post.bloom_intensity > 0.0 {
+        let bloom = textureSampleLevel(t_bloom, s_diffuse, uv, 0.0).rgb;
+        color = vec4<f32>(color.rgb + bloom * post.bloom_intensity, color.a);
+    }
+    if post.outline_thickness > 0.0 && on_outline(uv) {
+        color = vec4<f32>(post.outline_color, color.a);
+    }
+    return color;
+}
+
+// True if a neighbor outline_thickness pixels away is noticeably further away
+// Only the closer side of a depth jump is drawn, so outlines stay on the shape in front
+fn on_outline(uv: vec2<f32>) -> bool {
+    let offset = post.outline_thickness / vec2<f32>(textureDimensions(t_depth));
+    let limit = load_depth(uv) * (1.0 + outline_depth_threshold);
+    return load_depth(uv + vec2<f32>(offset.x, 0.0)) > limit
+        || load_depth(uv - vec2<f32>(offset.x, 0.0)) > limit
+        || load_depth(uv + vec2<f32>(0.0, offset.y)) > limit
+        || load_depth(uv - vec2<f32>(0.0, offset.y)) > limit;
+}
+
+// Circle of confusion radius in pixels of a surface at depth
+fn coc(depth: f32) -> f32 {
+    return min(post.aperture * abs(depth - post.focus_distance) / max(depth, 0.0001), max_coc);
+}
+
+fn load_depth(uv: vec2<f32>) -> f32 {
+    let dim = vec2<i32>(textureDimensions(t_depth));
+    let pixel = clamp(vec2<i32>(uv * vec2<f32>(dim)), vec2<i32>(0), dim - 1);
+    return textureLoad(t_depth, pixel, 0).x;
+}
+
+// Gathers texels on a disk the size of the circle of confusion
+fn dof_color(uv: vec2<f32>) -> vec4<f32> {
+    let color = textureSampleLevel(t_diffuse, s_diffuse, uv, 0.0);
+    let depth = load_depth(uv);
+    let radius = coc(depth);
+    if post.aperture <= 0.0 || radius < 0.5 {
+        return color;
+    }
+
+    let texel = 1.0 / vec2<f32>(textureDimensions(t_diffuse));
+    var sum = color;
+    var weight = 1.0;
+    for (var i = 0; i < dof_samples; i++) {
+        let r = sqrt((f32(i) + 0.5) / f32(dof_samples)) * radius;
+        let angle = f32(i) * golden_angle;
+        let sample_uv = uv + vec2<f32>(cos(angle), sin(angle)) * r * texel;
+        // Sharp surfaces in front do not bleed over blurred surfaces behind them
+        let sample_depth = load_depth(sample_uv);
+        let w = select(1.0, clamp(coc(sample_depth) - r + 1.0, 0.0, 1.0), sample_depth < depth);
+        sum += textureSampleLevel(t_diffuse, s_diffuse, sample_uv, 0.0) * w;
+        weight += w;
+    }
+    return sum / weight;
+}
+
+fn srgb_to_linear(color: vec3<f32>) -> vec3<f32> {
+    let low = color / 12.92;
+    let high = pow((color + 0.055) / 1.055, vec3<f32>(2.4));
+    return select(high, low, color <= vec3<f32>(0.04045));
 }
@@ -1,7 +1,13 @@
 use crate::{
-    context::Context, input::InputContext, render::RenderContext, time::TimeContext, window,
+    cmd,
+    context::Context,
+    error::RaymarcherError,
+    input::InputContext,
+    render::{RenderContext, HEIGHT, WIDTH},
+    time::TimeContext,
+    window,
 };
-use winit::event_loop::EventLoop;
+use winit::{event_loop::EventLoop, window::Icon};
 
 /// User callbaks
 pub trait Callbacks {
@@ -14,6 +20,58 @@ pub trait Callbacks {
     fn update(&mut self, _ctx: &mut Context, _dt: f32) -> bool {
         false
     }
+
+    /// Called after the window is resized
+    /// width, height: New window size in physical pixels, never zero
+    /// Minimizing does not resize since there is nothing to render to
+    fn resize(&mut self, _ctx: &mut Context, _width: u32, _height: u32) {}
+
+    /// Called once before the app exits, after the window is closed or update returns true
+    /// The context is still usable, e.g. to stop a recording or read back the last frame
+    fn exit(&mut self, _ctx: &mut Context) {}
+}
+
+/// Startup configuration passed to run_with_config
+#[derive(Debug, Clone)]
+pub struct RunConfig {
+    /// Graphics backends the adapter can be picked from
+    pub backends: wgpu::Backends,
+    /// Prefer the integrated (LowPower) or discrete (HighPerformance) gpu
+    pub power_preference: wgpu::PowerPreference,
+    /// Forces a software adapter
+    pub force_fallback: bool,
+    /// Initial window size and render resolution
+    pub width: u32,
+    pub height: u32,
+    /// WGSL source appended to the compute shader, defining the distance of Shape::Custom
+    /// Must define `fn sdf_custom(id: u32, p: vec3<f32>, v1: vec3<f32>, f1: f32) -> f32`
+    /// where p is relative to the position of the shape
+    /// None uses a default where custom shapes are never hit
+    pub custom_sdf: Option<String>,
+    /// Icon of the window, built with Icon::from_rgba
+    /// None keeps the platform default
+    pub icon: Option<Icon>,
+    /// Threads per compute workgroup as (x, y), each thread shades one pixel
+    /// GPUs schedule threads in groups of 32 or 64 so x * y should be a multiple of 64,
+    /// (8, 8) and (16, 16) are safe choices. x * y can be at most 256,
+    /// larger sizes fail with RaymarcherError::InvalidWorkgroupSize
+    /// The resolution does not need to be a multiple, pixels past the edge are skipped
+    pub workgroup_size: (u32, u32),
+}
+
+impl Default for RunConfig {
+    fn default() -> Self {
+        Self {
+            backends: wgpu::Backends::all(),
+            power_preference: wgpu::PowerPreference::default(),
+            force_fallback: false,
+            width: WIDTH,
+            height: HEIGHT,
+            custom_sdf: None,
+            icon: None,
+            workgroup_size: (8, 8),
+        }
+    }
 }
 
 /// Main App
@@ -31,6 +89,9 @@ where
     /// Returns true if app should exit
     pub(crate) fn update(&mut self, ctx: &mut Context) -> bool {
         let dt = ctx.time.update_time();
+        ctx.input.set_time(ctx.time.time_since_start());
+        #[cfg(feature = "gamepad")]
+        ctx.input.gamepad.poll();
 
         // Update callback
         if self.callbacks.update(ctx, dt) {
@@ -40,40 +101,122 @@ where
         ctx.input.keyboard.save_keys();
         ctx.input.keyboard.save_modifiers();
         ctx.input.mouse.save_buttons();
-        ctx.input.mouse.set_mouse_delta((0.0, 0.0));
+        ctx.input.gamepad.save_buttons();
+        ctx.input.mouse.reset_mouse_delta();
+        ctx.input.mouse.reset_scroll_delta();
+        ctx.input.clear_events();
 
         false
     }
 }
 
-/// Runs the event loop
+/// Runs the event loop with the default config
 /// Calls back to user defined functions thorugh Callback trait
-pub fn run<C: Callbacks + 'static>(callbacks: C)
+/// Returns an error if the gpu could not be initialized
+pub fn run<C>(callbacks: C) -> Result<(), RaymarcherError>
 where
     C: Callbacks + 'static,
 {
+    run_with_config(callbacks, RunConfig::default())
+}
+
+/// Runs the event loop with the given config
+/// Calls back to user defined functions thorugh Callback trait
+/// Returns an error if the gpu could not be initialized
+pub fn run_with_config<C>(callbacks: C, config: RunConfig) -> Result<(), RaymarcherError>
+where
+    C: Callbacks + 'static,
+{
+    assert!(
+        config.width != 0 && config.height != 0,
+        "screen dimensions can not be zero"
+    );
     env_logger::init();
     let app = App { callbacks };
 
-    let (mut ctx, event_loop) = pollster::block_on(build_context());
+    // Init errors must be surfaced before the event loop takes over
+    let (mut ctx, event_loop) = pollster::block_on(build_context(&config))?;
 
     app.callbacks.init(&mut ctx);
 
     pollster::block_on(window::run_window(event_loop, app, ctx));
+    Ok(())
+}
+
+/// Runs the app without a window for the given amount of frames with the default config
+/// Time advances by a fixed 1/60 seconds each frame so rendering is deterministic
+/// Returns the pixels of the last rendered frame
+pub fn run_headless<C>(
+    callbacks: C,
+    width: u32,
+    height: u32,
+    frames: u32,
+) -> Result<Vec<[u8; 4]>, RaymarcherError>
+where
+    C: Callbacks + 'static,
+{
+    let config = RunConfig {
+        width,
+        height,
+        ..Default::default()
+    };
+    run_headless_with_config(callbacks, config, frames)
+}
+
+/// Runs the app without a window for the given amount of frames with the given config
+/// The resolution is taken from the config, the icon is ignored
+/// Returns the pixels of the last rendered frame
+pub fn run_headless_with_config<C>(
+    callbacks: C,
+    config: RunConfig,
+    frames: u32,
+) -> Result<Vec<[u8; 4]>, RaymarcherError>
+where
+    C: Callbacks + 'static,
+{
+    assert!(
+        config.width != 0 && config.height != 0,
+        "screen dimensions can not be zero"
+    );
+    let _ = env_logger::try_init();
+    let mut app = App { callbacks };
+
+    let mut ctx = pollster::block_on(build_headless_context(&config))?;
+
+    app.callbacks.init(&mut ctx);
+
+    for _ in 0..frames {
+        if app.update(&mut ctx) {
+            break;
+        }
+        // Surface errors can not occur without a surface
+        ctx.render.render(&ctx.time).unwrap();
+        ctx.record_frame();
+    }
+    app.callbacks.exit(&mut ctx);
+
+    Ok(cmd::render::read_pixels(&ctx))
 }
 
 // TODO contex builder?
-async fn build_context() -> (Context, EventLoop<()>) {
-    let (window, event_loop) = window::new_window();
+async fn build_context(config: &RunConfig) -> Result<(Context, EventLoop<()>), RaymarcherError> {
+    let (window, event_loop) =
+        window::new_window(config.width, config.height, config.icon.clone())?;
 
     let time = TimeContext::default();
     let input = InputContext::default();
-    let render = RenderContext::new(window).await;
-    let context = Context {
-        render,
-        time,
-        input,
-    };
+    let render = RenderContext::new(Some(window), (config.width, config.height), config).await?;
+    let context = Context::new(render, time, input);
 
-    (context, event_loop)
+    Ok((context, event_loop))
+}
+
+async fn build_headless_context(config: &RunConfig) -> Result<Context, RaymarcherError> {
+    let time = TimeContext {
+        fixed_dt: Some(1.0 / 60.0),
+        ..Default::default()
+    };
+    let input = InputContext::default();
+    let render = RenderContext::new(None, (config.width, config.height), config).await?;
+    Ok(Context::new(render, time, input))
 }
@@ -0,0 +1,228 @@
+use glam::{Mat3, Quat, Vec3};
+use winit::event::VirtualKeyCode as KeyCode;
+
+use crate::cmd::{keyboard, mouse, render};
+use crate::context::Context;
+use crate::input::KeyModifier;
+
+/// Pitch is kept within this many degrees of straight up/down to avoid flipping the camera
+const MAX_PITCH: f32 = 89.0;
+/// Movement speed multiplier while shift is held
+const FAST_MOVE_MULTIPLIER: f32 = 3.0;
+
+/// State of a first person fly camera driven by fly_controller
+/// yaw and pitch are in degrees
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct FlyState {
+    pub pos: Vec3,
+    pub yaw: f32,
+    pub pitch: f32,
+}
+
+impl FlyState {
+    pub fn new(pos: Vec3) -> Self {
+        Self {
+            pos,
+            ..Default::default()
+        }
+    }
+
+    /// Rotates the camera by the given degrees, pitch is clamped to avoid flipping
+    pub fn look(&mut self, yaw: f32, pitch: f32) {
+        self.yaw += yaw;
+        self.pitch = (self.pitch + pitch).clamp(-MAX_PITCH, MAX_PITCH);
+    }
+
+    /// Returns the camera rotation matrix
+    pub fn rotation(&self) -> Mat3 {
+        Mat3::from_rotation_y(self.yaw.to_radians())
+            * Mat3::from_rotation_x(self.pitch.to_radians())
+    }
+}
+
+/// Moves the camera with WASD and rotates it with the mouse, holding shift moves faster
+/// Writes the resulting camera position and rotation to the renderer
+/// move_speed: Units per second
+/// look_speed: Degrees per pixel of mouse movement
+pub fn fly_controller(
+    ctx: &mut Context,
+    state: &mut FlyState,
+    move_speed: f32,
+    look_speed: f32,
+    dt: f32,
+) {
+    let (dx, dy) = mouse::mouse_delta(ctx);
+    state.look(dx * look_speed, dy * look_speed);
+    let rotation = state.rotation();
+
+    let mut movement = Vec3::ZERO;
+    if keyboard::key_pressed(ctx, KeyCode::W) {
+        movement += rotation.z_axis;
+    }
+    if keyboard::key_pressed(ctx, KeyCode::S) {
+        movement -= rotation.z_axis;
+    }
+    if keyboard::key_pressed(ctx, KeyCode::D) {
+        movement += rotation.x_axis;
+    }
+    if keyboard::key_pressed(ctx, KeyCode::A) {
+        movement -= rotation.x_axis;
+    }
+    let mut speed = move_speed;
+    if keyboard::modifier_pressed(ctx, KeyModifier::Shift) {
+        speed *= FAST_MOVE_MULTIPLIER;
+    }
+    state.pos += movement.normalize_or_zero() * speed * dt;
+
+    render::set_camera_rot(ctx, rotation);
+    render::set_camera_pos(ctx, state.pos);
+}
+
+/// Camera state at a point in time of a CameraPath
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CameraKeyframe {
+    /// Seconds from the start of the path
+    pub time: f32,
+    pub pos: Vec3,
+    pub rotation: Mat3,
+    pub focal_length: f32,
+}
+
+/// Keyframed camera movement, played back with play_path
+/// Keyframes are kept sorted by time
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct CameraPath {
+    keyframes: Vec<CameraKeyframe>,
+}
+
+impl CameraPath {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a keyframe, keyframes can be added in any order
+    pub fn add_keyframe(&mut self, time: f32, pos: Vec3, rotation: Mat3, focal_length: f32) {
+        let index = self
+            .keyframes
+            .partition_point(|keyframe| keyframe.time <= time);
+        self.keyframes.insert(
+            index,
+            CameraKeyframe {
+                time,
+                pos,
+                rotation,
+                focal_length,
+            },
+        );
+    }
+
+    pub fn keyframes(&self) -> &[CameraKeyframe] {
+        &self.keyframes
+    }
+
+    /// Returns the time of the last keyframe, 0 if there are none
+    pub fn duration(&self) -> f32 {
+        self.keyframes.last().map_or(0.0, |keyframe| keyframe.time)
+    }
+
+    /// Returns the camera state at time t, interpolated between the surrounding keyframes
+    /// Times before the first or after the last keyframe hold that keyframe
+    /// Returns None if the path has no keyframes
+    pub fn sample(&self, t: f32) -> Option<CameraKeyframe> {
+        let first = self.keyframes.first()?;
+        let last = self.keyframes.last()?;
+        if t <= first.time {
+            return Some(*first);
+        }
+        if t >= last.time {
+            return Some(*last);
+        }
+
+        let index = self
+            .keyframes
+            .partition_point(|keyframe| keyframe.time <= t);
+        let (from, to) = (&self.keyframes[index - 1], &self.keyframes[index]);
+        let s = (t - from.time) / (to.time - from.time);
+        // Matrices are interpolated as quaternions, lerping them directly skews the rotation
+        let rotation = Quat::from_mat3(&from.rotation)
+            .normalize()
+            .slerp(Quat::from_mat3(&to.rotation).normalize(), s);
+        Some(CameraKeyframe {
+            time: t,
+            pos: from.pos.lerp(to.pos, s),
+            rotation: Mat3::from_quat(rotation),
+            focal_length: from.focal_length + (to.focal_length - from.focal_length) * s,
+        })
+    }
+}
+
+/// Moves the camera to the state of path at time t
+/// Does nothing if the path has no keyframes
+pub fn play_path(ctx: &mut Context, path: &CameraPath, t: f32) {
+    let Some(keyframe) = path.sample(t) else {
+        return;
+    };
+    render::set_camera_pos(ctx, keyframe.pos);
+    render::set_camera_rot(ctx, keyframe.rotation);
+    render::set_focal_length(ctx, keyframe.focal_length);
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{EulerRot, Mat3, Vec3};
+
+    use super::{CameraPath, FlyState, MAX_PITCH};
+
+    #[test]
+    fn fly_state_pitch_clamp_test() {
+        let mut state = FlyState::new(Vec3::ZERO);
+        state.look(30.0, 200.0);
+        assert_eq!(state.yaw, 30.0);
+        assert_eq!(state.pitch, MAX_PITCH);
+
+        state.look(0.0, -400.0);
+        assert_eq!(state.pitch, -MAX_PITCH);
+
+        // Forward never flips past straight up/down
+        assert!(state.rotation().z_axis.y.abs() < 1.0);
+        assert!(state.rotation().y_axis.y > 0.0);
+    }
+
+    #[test]
+    fn fly_state_matches_euler_test() {
+        // Same convention as render::set_camera_euler without roll
+        let mut state = FlyState::new(Vec3::ZERO);
+        state.look(40.0, 25.0);
+        let euler = Mat3::from_euler(EulerRot::YXZ, 40f32.to_radians(), 25f32.to_radians(), 0.0);
+        assert!(state.rotation().abs_diff_eq(euler, 1e-6));
+        // Positive pitch looks down
+        assert!(state.rotation().z_axis.y < 0.0);
+    }
+
+    #[test]
+    fn camera_path_sample_test() {
+        let mut path = CameraPath::new();
+        assert_eq!(path.sample(0.0), None);
+
+        // Added out of order
+        path.add_keyframe(
+            2.0,
+            Vec3::X * 2.0,
+            Mat3::from_rotation_y(90f32.to_radians()),
+            3.0,
+        );
+        path.add_keyframe(0.0, Vec3::ZERO, Mat3::IDENTITY, 1.0);
+        assert_eq!(path.duration(), 2.0);
+
+        let mid = path.sample(1.0).unwrap();
+        assert!(mid.pos.abs_diff_eq(Vec3::X, 1e-6));
+        assert!((mid.focal_length - 2.0).abs() < 1e-6);
+        // Halfway rotation instead of a skewed average of the matrices
+        let expected = Mat3::from_rotation_y(45f32.to_radians());
+        assert!(mid.rotation.abs_diff_eq(expected, 1e-5));
+
+        // Holds the end keyframes outside the path
+        assert_eq!(path.sample(-1.0).unwrap().pos, Vec3::ZERO);
+        assert_eq!(path.sample(5.0).unwrap().pos, Vec3::X * 2.0);
+    }
+}
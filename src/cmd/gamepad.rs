@@ -0,0 +1,45 @@
+use crate::context::Context;
+use crate::input::{GamepadAxis, GamepadButton, GamepadId};
+
+/// Returns the ids of all connected gamepads in ascending order
+/// Empty if no gamepads are connected, gamepads are only polled with the gamepad feature
+pub fn gamepads(ctx: &Context) -> Vec<GamepadId> {
+    ctx.input.gamepad.gamepads()
+}
+
+/// Returns the ids of gamepads connected this frame
+pub fn just_connected(ctx: &Context) -> &[GamepadId] {
+    ctx.input.gamepad.just_connected()
+}
+
+/// Returns the ids of gamepads disconnected this frame
+pub fn just_disconnected(ctx: &Context) -> &[GamepadId] {
+    ctx.input.gamepad.just_disconnected()
+}
+
+/// Returns true if GamepadButton is pressed
+/// Accepts repeating
+pub fn button_pressed(ctx: &Context, id: GamepadId, button: GamepadButton) -> bool {
+    ctx.input.gamepad.button_pressed(id, button)
+}
+
+/// Returns true if GamepadButton was pressed this frame
+pub fn button_just_pressed(ctx: &Context, id: GamepadId, button: GamepadButton) -> bool {
+    ctx.input.gamepad.button_just_pressed(id, button)
+}
+
+/// Returns true if GamepadButton was released this frame
+pub fn button_released(ctx: &Context, id: GamepadId, button: GamepadButton) -> bool {
+    ctx.input.gamepad.button_released(id, button)
+}
+
+/// Returns the value of GamepadAxis in [-1, 1], 0 within the deadzone
+pub fn axis_value(ctx: &Context, id: GamepadId, axis: GamepadAxis) -> f32 {
+    ctx.input.gamepad.axis_value(id, axis)
+}
+
+/// Sets the axis value below which sticks are considered centered, defaults to 0.1
+pub fn set_deadzone(ctx: &mut Context, deadzone: f32) {
+    debug_assert!((0.0..1.0).contains(&deadzone), "deadzone must be in [0, 1)");
+    ctx.input.gamepad.deadzone = deadzone;
+}
@@ -0,0 +1,8 @@
+use crate::context::Context;
+use crate::input::InputEvent;
+
+/// Returns the input events received since the last frame in the order they occurred
+/// Unlike the polled state, presses and releases within the same frame are all kept
+pub fn events(ctx: &Context) -> &[InputEvent] {
+    ctx.input.events()
+}
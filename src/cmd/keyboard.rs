@@ -1,5 +1,5 @@
 use crate::context::Context;
-use crate::input::{KeyCode, KeyModifier};
+use crate::input::{InputBinding, KeyCode, KeyModifier};
 
 /// Returns true if KeyCode is pressed
 /// Accepts repeating
@@ -17,6 +17,12 @@ pub fn key_released(ctx: &Context, keycode: KeyCode) -> bool {
     ctx.input.keyboard.key_released(keycode)
 }
 
+/// Returns the seconds since KeyCode was first pressed
+/// Returns 0 if KeyCode is not pressed
+pub fn key_held_duration(ctx: &Context, keycode: KeyCode) -> f32 {
+    ctx.input.keyboard.key_held_duration(keycode)
+}
+
 /// Returns true if KeyModifer is pressed
 /// Accepts repeating
 pub fn modifier_pressed(ctx: &Context, key_modifier: KeyModifier) -> bool {
@@ -32,3 +38,25 @@ pub fn modifer_just_pressed(ctx: &Context, key_modifier: KeyModifier) -> bool {
 pub fn modifer_released(ctx: &Context, key_modifier: KeyModifier) -> bool {
     ctx.input.keyboard.modifier_released(key_modifier)
 }
+
+/// Binds action to keys and mouse buttons, replacing any previous bindings
+/// Lets controls be rebound without changing the code checking the action
+pub fn bind_action(ctx: &mut Context, action: &str, bindings: &[InputBinding]) {
+    ctx.input.bind_action(action, bindings);
+}
+
+/// Returns true if any input bound to action is pressed
+/// Accepts repeating
+pub fn action_pressed(ctx: &Context, action: &str) -> bool {
+    ctx.input.action_pressed(action)
+}
+
+/// Returns true if action was pressed this frame
+pub fn action_just_pressed(ctx: &Context, action: &str) -> bool {
+    ctx.input.action_just_pressed(action)
+}
+
+/// Returns true if action was released this frame
+pub fn action_released(ctx: &Context, action: &str) -> bool {
+    ctx.input.action_released(action)
+}
@@ -1,3 +1,6 @@
+pub mod camera;
+pub mod gamepad;
+pub mod input;
 pub mod keyboard;
 pub mod mouse;
 pub mod render;
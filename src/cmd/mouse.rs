@@ -18,7 +18,8 @@ pub fn mouse_pos_physical(ctx: &Context) -> (f64, f64) {
     ctx.input.mouse.mouse_pos_physical()
 }
 
-/// Returns the current pixel under the mouse
+/// Returns the current pixel under the mouse in render resolution coordinates
+/// Clamped to the edge pixels while the mouse is outside the window, see mouse_on_screen
 pub fn mouse_pos_pixel(ctx: &Context) -> (u32, u32) {
     ctx.input.mouse.mouse_pos_pixel(&ctx.render)
 }
@@ -39,8 +40,52 @@ pub fn mouse_button_released(ctx: &Context, keycode: MouseButton) -> bool {
     ctx.input.mouse.button_released(keycode)
 }
 
+/// Returns the seconds since MouseButton was first pressed
+/// Returns 0 if MouseButton is not pressed
+pub fn mouse_button_held_duration(ctx: &Context, keycode: MouseButton) -> f32 {
+    ctx.input.mouse.button_held_duration(keycode)
+}
+
+/// Returns true if MouseButton was double clicked this frame
+pub fn mouse_button_double_clicked(ctx: &Context, keycode: MouseButton) -> bool {
+    ctx.input.mouse.button_double_clicked(keycode)
+}
+
+/// Sets the max seconds between two clicks of a double click, defaults to 0.3
+pub fn set_double_click_time(ctx: &mut Context, seconds: f32) {
+    debug_assert!(seconds >= 0.0, "double click time can not be negative");
+    ctx.input.mouse.double_click_time = seconds;
+}
+
+/// Returns the (dx, dy) motion since MouseButton was pressed
+/// Returns None if MouseButton is not pressed
+pub fn drag_delta(ctx: &Context, keycode: MouseButton) -> Option<(f32, f32)> {
+    ctx.input
+        .mouse
+        .drag_delta(keycode)
+        .map(|(dx, dy)| (dx as f32, dy as f32))
+}
+
+/// Returns true if MouseButton is held and the mouse has moved more than
+/// threshold physical pixels since the press, separating drags from clicks
+pub fn is_dragging(ctx: &Context, keycode: MouseButton, threshold: f32) -> bool {
+    ctx.input.mouse.is_dragging(keycode, threshold as f64)
+}
+
 /// Returns the scroll delta for the current frame
 pub fn scroll_delta(ctx: &Context) -> (f32, f32) {
     let (dx, dy) = ctx.input.mouse.scroll_delta();
     (dx as f32, dy as f32)
 }
+
+/// Returns the sum of all scroll deltas since start or the last reset_scroll_total
+/// Useful for absolute values such as zoom levels
+pub fn scroll_total(ctx: &Context) -> (f32, f32) {
+    let (x, y) = ctx.input.mouse.scroll_total();
+    (x as f32, y as f32)
+}
+
+/// Resets the scroll total to zero
+pub fn reset_scroll_total(ctx: &mut Context) {
+    ctx.input.mouse.reset_scroll_total();
+}
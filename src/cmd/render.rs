@@ -1,6 +1,35 @@
-use glam::{uvec2, Mat3, Vec3};
+use std::path::Path;
 
-use crate::{render::MAX_SHAPE_AMOUNT, Context, Shape};
+use glam::{EulerRot, Mat3, Quat, Vec3};
+
+use crate::{
+    error::RaymarcherError,
+    recording::Recording,
+    render::{
+        f16_to_f32, load_png, look_at_rotation, DIRECTIONAL_LIGHT, MAX_MATERIAL_AMOUNT, POINT_LIGHT,
+    },
+    Context, DebugMode, FilterMode, Material, MaterialId, Shape, ShapeHandle, Tonemap,
+};
+
+/// Returns the gpu time of the most recently measured compute pass in milliseconds
+/// Lags a few frames behind since results are read back without stalling
+/// None if the adapter does not support timestamp queries or nothing was measured yet
+pub fn last_compute_time_ms(ctx: &Context) -> Option<f32> {
+    ctx.render.gpu_timer.as_ref()?.last_ms
+}
+
+/// Returns the wgpu device used by the raymarcher, e.g. for allocating extra buffers
+/// Creating resources and running separate passes is fine, changing the raymarcher's own
+/// pipelines, buffers or textures through it is unsupported
+pub fn device(ctx: &Context) -> &wgpu::Device {
+    &ctx.render.device
+}
+
+/// Returns the wgpu queue used by the raymarcher
+/// Work submitted here is ordered with the raymarcher's own submissions
+pub fn queue(ctx: &Context) -> &wgpu::Queue {
+    &ctx.render.queue
+}
 
 /// Sets the internal camera position
 pub fn set_camera_pos(ctx: &mut Context, pos: Vec3) {
@@ -12,29 +41,344 @@ pub fn set_camera_rot(ctx: &mut Context, rot: Mat3) {
     ctx.render.globals.camera_rot = rot;
 }
 
+/// Sets the camera rotation from a quaternion, converted with Mat3::from_quat
+pub fn set_camera_rotation_quat(ctx: &mut Context, quat: Quat) {
+    debug_assert!(quat.is_normalized(), "camera quaternion must be normalized");
+    ctx.render.globals.camera_rot = Mat3::from_quat(quat);
+}
+
+/// Sets the camera rotation from euler angles in radians
+/// Rotates by yaw around y, then pitch around the rotated x and finally roll around the view
+/// direction, i.e. Mat3::from_euler(EulerRot::YXZ, yaw, pitch, roll)
+/// The camera looks along +z with +y up, positive pitch looks down
+pub fn set_camera_euler(ctx: &mut Context, yaw: f32, pitch: f32, roll: f32) {
+    ctx.render.globals.camera_rot = Mat3::from_euler(EulerRot::YXZ, yaw, pitch, roll);
+}
+
 /// Sets the internal camera focal length
 pub fn set_focal_length(ctx: &mut Context, focal_length: f32) {
     debug_assert!(focal_length > 0.0, "focal length must be greater than 0");
     ctx.render.globals.focal_length = focal_length;
 }
 
+/// Places the camera at eye looking towards target
+/// up: Approximate up direction of the camera, an alternate is used if parallel to the view direction
+pub fn look_at(ctx: &mut Context, eye: Vec3, target: Vec3, up: Vec3) {
+    assert!(eye != target, "eye and target can not be the same");
+    ctx.render.globals.camera_pos = eye;
+    ctx.render.globals.camera_rot = look_at_rotation(target - eye, up);
+}
+
+/// Returns the internal camera position
+pub fn get_camera_pos(ctx: &Context) -> Vec3 {
+    ctx.render.globals.camera_pos
+}
+
+/// Returns the internal camera rotation
+pub fn get_camera_rot(ctx: &Context) -> Mat3 {
+    ctx.render.globals.camera_rot
+}
+
+/// Returns the internal camera focal length
+pub fn get_focal_length(ctx: &Context) -> f32 {
+    ctx.render.globals.focal_length
+}
+
+/// Uses a point light at the given position
+/// Replaces any active directional light
+pub fn set_point_light(ctx: &mut Context, pos: Vec3, color: Vec3) {
+    ctx.render.globals.light_type = POINT_LIGHT;
+    ctx.render.globals.light_pos = pos;
+    ctx.render.globals.light_color = color;
+}
+
+/// Uses a directional light shining in the given direction, such as the sun
+/// Replaces any active point light
+pub fn set_directional_light(ctx: &mut Context, direction: Vec3, color: Vec3) {
+    assert!(direction != Vec3::ZERO, "light direction can not be zero");
+    ctx.render.globals.light_type = DIRECTIONAL_LIGHT;
+    ctx.render.globals.light_dir = direction.normalize();
+    ctx.render.globals.light_color = color;
+}
+
+/// Sets the color of the active light
+/// Defaults to white
+pub fn set_light_color(ctx: &mut Context, color: Vec3) {
+    ctx.render.globals.light_color = color;
+}
+
+/// Sets the constant light added to every surface, dimmed only by ambient occlusion
+/// Keeps areas no light reaches from being fully black, defaults to a dim white of 0.05
+pub fn set_ambient(ctx: &mut Context, color: Vec3) {
+    ctx.render.globals.ambient_color = color;
+}
+
+/// Enables/Disables a checker pattern on all planes, replacing their material color
+/// scale: Side length of a checker cell in world units
+pub fn set_plane_checker(
+    ctx: &mut Context,
+    enabled: bool,
+    scale: f32,
+    color_a: Vec3,
+    color_b: Vec3,
+) {
+    debug_assert!(scale > 0.0, "checker scale must be greater than 0");
+    ctx.render.globals.checker_enabled = enabled as u32;
+    ctx.render.globals.checker_scale = scale;
+    ctx.render.globals.checker_color_a = color_a;
+    ctx.render.globals.checker_color_b = color_b;
+}
+
+/// Fades surfaces towards color by 1 - exp(-density * distance)
+/// The background is also set to color, a density of 0 disables fog
+pub fn set_fog(ctx: &mut Context, color: Vec3, density: f32) {
+    debug_assert!(density >= 0.0, "fog density can not be negative");
+    ctx.render.globals.fog_color = color;
+    ctx.render.globals.fog_density = density;
+}
+
+/// Enables/Disables a grid on the XZ plane at y = 0 for spatial reference
+/// Drawn over the background but hidden behind geometry
+/// spacing: Distance between grid lines in world units
+pub fn set_grid(ctx: &mut Context, enabled: bool, spacing: f32, color: Vec3) {
+    debug_assert!(spacing > 0.0, "grid spacing must be greater than 0");
+    ctx.render.globals.grid_enabled = enabled as u32;
+    ctx.render.globals.grid_spacing = spacing;
+    ctx.render.globals.grid_color = color;
+}
+
+/// Adds color to surfaces seen at grazing angles, outlining shapes against the background
+/// power: Tightness of the rim, higher values give thinner rims
+/// A color of zero disables it
+pub fn set_rim_light(ctx: &mut Context, color: Vec3, power: f32) {
+    debug_assert!(power > 0.0, "rim power must be greater than 0");
+    ctx.render.globals.rim_color = color;
+    ctx.render.globals.rim_power = power;
+}
+
+/// Sets the amount of jittered rays cast per pixel, 1 disables antialiasing
+/// Must be a perfect square such as 1, 4, 9 or 16
+/// Render cost scales linearly with the amount of samples
+pub fn set_antialiasing(ctx: &mut Context, samples: u32) {
+    let side = (samples as f32).sqrt().round() as u32;
+    assert!(
+        samples > 0 && side * side == samples,
+        "antialiasing samples must be a perfect square"
+    );
+    ctx.render.globals.aa_samples = samples;
+}
+
+/// Sets the seed of the noise used to jitter samples, 0 by default
+/// Rendering is deterministic for a given seed, so headless frames can be compared byte for byte
+pub fn set_seed(ctx: &mut Context, seed: u32) {
+    ctx.render.globals.seed = seed;
+}
+
+/// Sets the raymarching parameters
+/// max_steps: Maximum amount of steps per ray
+/// max_distance: Distance at which a ray is considered a miss
+/// surface_epsilon: Distance at which a ray is considered a hit
+pub fn set_march_params(
+    ctx: &mut Context,
+    max_steps: u32,
+    max_distance: f32,
+    surface_epsilon: f32,
+) {
+    debug_assert!(max_steps > 0, "max steps must be greater than 0");
+    debug_assert!(
+        surface_epsilon > 0.0,
+        "surface epsilon must be greater than 0"
+    );
+    ctx.render.globals.max_steps = max_steps;
+    ctx.render.globals.max_dist = max_distance;
+    ctx.render.globals.surface_dist = surface_epsilon;
+}
+
+/// Sets the offset used to estimate surface normals from the distance gradient
+/// Too small values amplify floating point noise, too large values round off fine detail
+/// Scale it with the size of the scene, defaults to 0.00001
+pub fn set_normal_epsilon(ctx: &mut Context, epsilon: f32) {
+    debug_assert!(epsilon > 0.0, "normal epsilon must be greater than 0");
+    ctx.render.globals.normal_epsilon = epsilon;
+}
+
+/// Sets the maximum amount of reflection bounces per ray
+/// 0 disables reflections
+pub fn set_max_bounces(ctx: &mut Context, max_bounces: u32) {
+    ctx.render.globals.max_bounces = max_bounces;
+}
+
+/// Sets how far along the surface normal shadow rays start, 0.005 by default
+/// Too small and surfaces shadow themselves in speckles (acne)
+/// Too large and shadows detach from the shapes casting them and thin shapes stop casting any
+pub fn set_shadow_bias(ctx: &mut Context, bias: f32) {
+    debug_assert!(bias >= 0.0, "shadow bias can not be negative");
+    ctx.render.globals.shadow_bias = bias;
+}
+
+/// Sets how many times a ray may reflect internally inside a refracting shape
+/// Rays still trapped afterwards leave in their current direction
+pub fn set_max_internal_reflections(ctx: &mut Context, max_reflections: u32) {
+    ctx.render.globals.max_internal_reflections = max_reflections;
+}
+
+/// Enables or disables linear to srgb conversion of the rendered colors
+/// Enabled by default, disabling it makes the image appear too dark
+pub fn set_gamma_correction(ctx: &mut Context, enabled: bool) {
+    ctx.render.globals.gamma_correction = enabled as u32;
+}
+
+/// Sets what the compute shader outputs, DebugMode::None for regular shading
+pub fn set_debug_mode(ctx: &mut Context, mode: DebugMode) {
+    ctx.render.globals.debug_mode = mode as u32;
+}
+
+/// Sets how shaded colors above 1 are mapped into the displayable range
+/// Applied to regular shading before gamma correction, Tonemap::None clips
+pub fn set_tonemap(ctx: &mut Context, mode: Tonemap) {
+    ctx.render.globals.tonemap = mode as u32;
+}
+
+/// Multiplies shaded colors before tone mapping to brighten or darken the scene
+/// Defaults to 1
+pub fn set_exposure(ctx: &mut Context, exposure: f32) {
+    assert!(exposure > 0.0, "exposure must be greater than 0");
+    ctx.render.globals.exposure = exposure;
+}
+
+/// Darkens the image towards the screen edges, a strength of 0 disables it
+/// strength: Amount of darkening at the edges in [0, 1]
+/// radius: Distance from the center where darkening starts, 1 is the screen edge
+pub fn set_vignette(ctx: &mut Context, strength: f32, radius: f32) {
+    debug_assert!(
+        (0.0..=1.0).contains(&strength),
+        "vignette strength must be in [0, 1]"
+    );
+    debug_assert!(radius >= 0.0, "vignette radius can not be negative");
+    ctx.render.globals.vignette_strength = strength;
+    ctx.render.globals.vignette_radius = radius;
+}
+
+/// Returns the (width, height) in pixels of the render texture
+/// Differs from the window size when a render scale is set or after resize
+pub fn resolution(ctx: &Context) -> (u32, u32) {
+    ctx.render.resolution
+}
+
 /// Resizes the render texture
 pub fn resize(ctx: &mut Context, width: u32, height: u32) {
     debug_assert!(
-        width != 0 || height != 0,
+        width != 0 && height != 0,
         "screen dimensions can not be zero"
     );
-    ctx.render.globals.screen_dim = uvec2(width, height);
-    // TODO resize render texture
+    ctx.render.set_resolution((width, height));
 }
 
-pub fn render_shape(ctx: &mut Context, shape: Shape) {
+/// Blurs surfaces away from the focus distance based on the depth of the hit
+/// aperture: Blur radius in pixels of surfaces infinitely far away, capped at 8, 0 disables it
+/// Applied when presenting to the window, read_pixels returns the unblurred frame
+pub fn set_dof(ctx: &mut Context, focus_distance: f32, aperture: f32) {
     debug_assert!(
-        ctx.render.shapes.len() < MAX_SHAPE_AMOUNT as usize,
-        "can not add more shapes than max: {}",
-        MAX_SHAPE_AMOUNT
+        focus_distance > 0.0,
+        "focus distance must be greater than 0"
     );
+    debug_assert!(aperture >= 0.0, "aperture can not be negative");
+    ctx.render.post_globals.focus_distance = focus_distance;
+    ctx.render.post_globals.aperture = aperture;
+    ctx.render.update_post_uniforms();
+}
+
+/// Enables/Disables skipping shapes which can not affect the frame before upload
+/// Shapes outside the view are kept while they can still cast shadows into it, and only distant
+/// shapes are skipped while reflective or transparent materials exist
+/// Only applies to top level shapes, CSG operands are always kept with their parent
+/// Disabled by default
+pub fn set_culling(ctx: &mut Context, enabled: bool) {
+    ctx.render.culling = enabled;
+}
+
+/// Enables/Disables averaging jittered frames while nothing changes
+/// Gives progressively cleaner antialiasing for still images
+/// Anything moving smears, call reset_accumulation whenever the camera or scene changes
+pub fn set_accumulation(ctx: &mut Context, enabled: bool) {
+    ctx.render.set_accumulation(enabled);
+}
+
+/// Restarts the accumulated average from the next frame
+pub fn reset_accumulation(ctx: &mut Context) {
+    ctx.render.accumulated_frames = 0;
+}
+
+/// Adds a glow around pixels brighter than threshold when presenting
+/// threshold: Displayed brightness in [0, 1] above which pixels bloom
+/// intensity: Strength of the added glow, 0 (default) disables bloom
+/// Like depth of field it is not part of read_pixels
+pub fn set_bloom(ctx: &mut Context, threshold: f32, intensity: f32) {
+    debug_assert!(intensity >= 0.0, "bloom intensity can not be negative");
+    ctx.render.post_globals.bloom_threshold = threshold;
+    ctx.render.post_globals.bloom_intensity = intensity;
+    ctx.render.update_post_uniforms();
+}
+
+/// Draws color along the silhouettes of shapes when presenting, e.g. for a toon look
+/// Edges are found where the depth jumps between neighboring pixels,
+/// so shapes in front of other shapes are outlined as well
+/// thickness: Width of the outline in pixels of the render resolution, 0 (default) disables it
+/// Negative thicknesses are clamped to 0
+/// Like bloom it is not part of read_pixels
+pub fn set_outline(ctx: &mut Context, color: Vec3, thickness: f32) {
+    ctx.render.post_globals.outline_color = color;
+    ctx.render.post_globals.outline_thickness = thickness.max(0.0);
+    ctx.render.update_post_uniforms();
+}
+
+/// Sets how the render texture is filtered when stretched over the window
+/// Linear (default) smooths upscaled renders, Nearest keeps hard pixel edges
+pub fn set_texture_filter(ctx: &mut Context, filter: FilterMode) {
+    ctx.render.set_texture_filter(filter);
+}
+
+/// Sets the linear color the window is cleared to before presenting the render texture
+/// Only visible where the render texture does not cover the window, e.g. while resizing
+/// Unrelated to the background color of the raymarched scene
+pub fn set_clear_color(ctx: &mut Context, color: Vec3) {
+    ctx.render.clear_color = wgpu::Color {
+        r: color.x as f64,
+        g: color.y as f64,
+        b: color.z as f64,
+        a: 1.0,
+    };
+}
+
+/// Renders at scale times the window size, e.g. 0.5 for half resolution
+/// The result is upscaled to fill the window, a scale of 1 renders at window size
+pub fn set_render_scale(ctx: &mut Context, scale: f32) {
+    assert!(scale > 0.0, "render scale must be greater than 0");
+    ctx.render.set_render_scale(scale);
+}
+
+/// Returns the index of the shape under pixel (px, py) or None on a miss
+/// Index refers to the order shapes were submitted this frame
+/// Shapes are cleared after rendering, so call this after submitting the current frame's shapes
+pub fn pick(ctx: &Context, px: u32, py: u32) -> Option<u32> {
+    ctx.render.pick(px, py)
+}
+
+/// Renders shape for the current frame
+/// There is no limit on the amount of shapes, the gpu buffer grows when needed
+/// Panics if the shape is nested deeper than MAX_SHAPE_DEPTH, see try_render_shape
+pub fn render_shape(ctx: &mut Context, shape: Shape) {
+    if let Err(err) = try_render_shape(ctx, shape) {
+        panic!("{err}");
+    }
+}
+
+/// Renders shape for the current frame
+/// Returns an error instead if the shape is nested deeper than MAX_SHAPE_DEPTH
+pub fn try_render_shape(ctx: &mut Context, shape: Shape) -> Result<(), RaymarcherError> {
+    shape.check_depth()?;
     ctx.render.shapes.push(shape);
+    Ok(())
 }
 
 pub fn render_shapes(ctx: &mut Context, shapes: Vec<Shape>) {
@@ -42,3 +386,222 @@ pub fn render_shapes(ctx: &mut Context, shapes: Vec<Shape>) {
         render_shape(ctx, shape);
     }
 }
+
+/// Renders a copy of shape at each offset from its position for the current frame
+/// Much cheaper than rendering the copies separately, see Shape::Instances
+/// Panics if shape is not a single primitive, optionally with a material
+pub fn render_instanced(ctx: &mut Context, shape: Shape, offsets: &[Vec3]) {
+    assert!(
+        shape.node_count() == 1,
+        "instanced shapes must be a single primitive"
+    );
+    render_shape(
+        ctx,
+        Shape::Instances {
+            shape: Box::new(shape),
+            offsets: offsets.to_vec(),
+        },
+    );
+}
+
+/// Adds a shape which is rendered every frame until removed
+/// Returns a handle which can be used to remove the shape
+/// Panics if the shape is nested deeper than MAX_SHAPE_DEPTH, see Shape::max_depth
+pub fn add_static_shape(ctx: &mut Context, shape: Shape) -> ShapeHandle {
+    if let Err(err) = shape.check_depth() {
+        panic!("{err}");
+    }
+    ctx.render.static_shapes.add(shape)
+}
+
+/// Removes a static shape
+/// Returns the removed shape or None if the handle is stale
+pub fn remove_static_shape(ctx: &mut Context, handle: ShapeHandle) -> Option<Shape> {
+    ctx.render.static_shapes.remove(handle)
+}
+
+/// Replaces a static shape while keeping its handle
+/// Returns false if the handle is stale
+pub fn set_static_shape(ctx: &mut Context, handle: ShapeHandle, shape: Shape) -> bool {
+    ctx.render.static_shapes.set(handle, shape)
+}
+
+/// Moves a static shape to pos relative to the position it was added at
+/// Returns false if the handle is stale
+pub fn set_shape_transform(ctx: &mut Context, handle: ShapeHandle, pos: Vec3) -> bool {
+    ctx.render.static_shapes.set_translation(handle, pos)
+}
+
+/// Removes all static shapes
+pub fn clear_static_shapes(ctx: &mut Context) {
+    ctx.render.static_shapes.clear();
+}
+
+/// Adds material to the material table
+/// Returns an id which can be applied to shapes with Shape::Material
+pub fn add_material(ctx: &mut Context, material: Material) -> MaterialId {
+    assert!(
+        (ctx.render.materials.len() as u64) < MAX_MATERIAL_AMOUNT,
+        "max amount of materials reached"
+    );
+    ctx.render.add_material(material)
+}
+
+/// Loads a png as the texture of materials with a texture_scale above 0
+/// The texture is shared by all textured materials and replaces the previous one
+pub fn set_texture(ctx: &mut Context, image_path: impl AsRef<Path>) -> Result<(), RaymarcherError> {
+    let (width, height, rgba) = load_png(image_path.as_ref())?;
+    check_texture_size(ctx, width, height)?;
+    ctx.render.set_surface_texture(width, height, &rgba);
+    Ok(())
+}
+
+/// Loads an equirectangular png which missed and reflected rays sample by direction
+/// Replaces the solid background, shapes still fade into the fog color
+pub fn set_environment(
+    ctx: &mut Context,
+    image_path: impl AsRef<Path>,
+) -> Result<(), RaymarcherError> {
+    let (width, height, rgba) = load_png(image_path.as_ref())?;
+    check_texture_size(ctx, width, height)?;
+    ctx.render.set_environment_texture(width, height, &rgba);
+    Ok(())
+}
+
+/// Returns to the solid background after set_environment
+pub fn clear_environment(ctx: &mut Context) {
+    ctx.render.globals.environment_enabled = 0;
+}
+
+/// Sets the texture of textured materials from 8 bit srgb rgba pixels in row-major order
+/// Useful for procedural textures, see set_texture
+/// Returns an error if the size is empty or larger than the device supports
+pub fn set_texture_rgba(
+    ctx: &mut Context,
+    width: u32,
+    height: u32,
+    rgba: &[u8],
+) -> Result<(), RaymarcherError> {
+    check_texture_size(ctx, width, height)?;
+    let len = (width as usize)
+        .checked_mul(height as usize)
+        .and_then(|pixels| pixels.checked_mul(4));
+    assert_eq!(
+        Some(rgba.len()),
+        len,
+        "texture data does not match its size"
+    );
+    ctx.render.set_surface_texture(width, height, rgba);
+    Ok(())
+}
+
+/// Checks that a texture of the given size can be created on the device
+fn check_texture_size(ctx: &Context, width: u32, height: u32) -> Result<(), RaymarcherError> {
+    let max_size = ctx.render.device.limits().max_texture_dimension_2d;
+    if width == 0 || height == 0 || width > max_size || height > max_size {
+        return Err(RaymarcherError::InvalidTextureSize((width, height)));
+    }
+    Ok(())
+}
+
+/// Reads back the most recently rendered frame
+/// Returns RGBA pixels in row-major order at the current resolution
+/// Colors are srgb encoded unless gamma correction is disabled
+pub fn read_pixels(ctx: &Context) -> Vec<[u8; 4]> {
+    let bytes = ctx.render.read_texture(&ctx.render.texture, 4);
+    bytes
+        .chunks_exact(4)
+        .map(|p| [p[0], p[1], p[2], p[3]])
+        .collect()
+}
+
+/// Writes the next frame_count rendered frames into dir as frame_00000.png, frame_00001.png, ...
+/// fixed_dt: Seconds the time advances each frame while recording, making the output independent
+/// of how fast frames are rendered. None keeps the current timestep
+/// Every frame is read back from the gpu before continuing, so recording runs slower than real time
+/// Like read_pixels, depth of field and bloom are not part of the recorded frames
+/// An active recording is replaced
+pub fn start_recording(
+    ctx: &mut Context,
+    dir: impl AsRef<Path>,
+    frame_count: u32,
+    fixed_dt: Option<f32>,
+) -> Result<(), RaymarcherError> {
+    assert!(frame_count > 0, "frame count must be greater than 0");
+    std::fs::create_dir_all(dir.as_ref())
+        .map_err(|err| RaymarcherError::RecordingFailed(err.into()))?;
+    stop_recording(ctx);
+    let prev_fixed_dt = ctx.time.fixed_dt;
+    if fixed_dt.is_some() {
+        ctx.time.fixed_dt = fixed_dt;
+    }
+    ctx.recording = Some(Recording {
+        dir: dir.as_ref().to_path_buf(),
+        frame: 0,
+        frame_count,
+        prev_fixed_dt,
+    });
+    Ok(())
+}
+
+/// Stops recording before all frames are written and restores the previous timestep
+pub fn stop_recording(ctx: &mut Context) {
+    ctx.stop_recording();
+}
+
+/// Returns true while frames are being recorded
+pub fn is_recording(ctx: &Context) -> bool {
+    ctx.recording.is_some()
+}
+
+/// Reads back the distance from the camera to the first hit of the most recent frame
+/// Returns one value per pixel in row-major order at the current resolution
+/// Missed rays are set to the max distance of the march parameters
+pub fn read_depth(ctx: &Context) -> Vec<f32> {
+    let bytes = ctx.render.read_texture(&ctx.render.depth_texture, 4);
+    bytes
+        .chunks_exact(4)
+        .map(|p| f32::from_ne_bytes([p[0], p[1], p[2], p[3]]))
+        .collect()
+}
+
+/// Reads back the index of the shape at the first hit of the most recent frame
+/// Returns one value per pixel in row-major order at the current resolution
+/// Indices match pick, missed rays are set to u32::MAX
+pub fn read_ids(ctx: &Context) -> Vec<u32> {
+    let bytes = ctx.render.read_texture(&ctx.render.id_texture, 4);
+    bytes
+        .chunks_exact(4)
+        .map(|p| u32::from_ne_bytes([p[0], p[1], p[2], p[3]]))
+        .collect()
+}
+
+/// Returns the index of the shape under pixel (px, py) of the most recent frame or None on a miss
+/// Same indices as pick, but read back from the gpu so the result matches the rendered frame
+/// exactly, e.g. for custom shapes the cpu can not evaluate
+/// Waits for the gpu, so unlike pick it should be called after rendering, e.g. in the next update
+pub fn pick_gpu(ctx: &Context, px: u32, py: u32) -> Option<u32> {
+    let (width, height) = ctx.render.resolution;
+    if px >= width || py >= height {
+        return None;
+    }
+    let bytes = ctx
+        .render
+        .read_texture_region(&ctx.render.id_texture, (px, py), (1, 1), 4);
+    let id = u32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    (id != u32::MAX).then_some(id)
+}
+
+/// Reads back the world space surface normal at the first hit of the most recent frame
+/// Returns one [x, y, z, 1] value per pixel in row-major order at the current resolution
+/// Missed rays are set to zero
+pub fn read_normals(ctx: &Context) -> Vec<[f32; 4]> {
+    let bytes = ctx.render.read_texture(&ctx.render.normal_texture, 8);
+    bytes
+        .chunks_exact(8)
+        .map(|p| {
+            let channel = |i: usize| f16_to_f32(u16::from_ne_bytes([p[2 * i], p[2 * i + 1]]));
+            [channel(0), channel(1), channel(2), channel(3)]
+        })
+        .collect()
+}
@@ -11,3 +11,36 @@ pub fn time_since_start(ctx: &Context) -> f32 {
 pub fn current_time(ctx: &Context) -> time::SystemTime {
     ctx.time.current_time
 }
+
+/// Returns the average frame time in seconds over the most recent frames
+pub fn frame_time(ctx: &Context) -> f32 {
+    ctx.time.average_frame_time()
+}
+
+/// Returns the average frames per second over the most recent frames
+/// Returns 0 before the first frame
+pub fn fps(ctx: &Context) -> f32 {
+    let frame_time = ctx.time.average_frame_time();
+    if frame_time > 0.0 {
+        1.0 / frame_time
+    } else {
+        0.0
+    }
+}
+
+/// Returns the scaled simulation time which drives shader animation
+pub fn scaled_time(ctx: &Context) -> f32 {
+    ctx.time.scaled_time
+}
+
+/// Sets the speed of the simulation time, 0 freezes, negative runs backwards
+/// dt passed to update is not affected
+pub fn set_time_scale(ctx: &mut Context, scale: f32) {
+    ctx.time.time_scale = scale;
+}
+
+/// Pauses/Resumes the simulation time
+/// dt passed to update is not affected
+pub fn pause(ctx: &mut Context, paused: bool) {
+    ctx.time.paused = paused;
+}
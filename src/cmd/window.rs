@@ -1,8 +1,8 @@
-use crate::Context;
+use crate::{Context, RaymarcherError};
 use wgpu::PresentMode;
 use winit::{
     dpi::PhysicalSize,
-    window::{CursorGrabMode, Fullscreen},
+    window::{CursorGrabMode, Fullscreen, Icon},
 };
 
 /// Enables/Disables vsync
@@ -15,6 +15,50 @@ pub fn set_vsync(ctx: &mut Context, vsync: bool) {
     ctx.render.reconfigure_present_mode(present_mode);
 }
 
+/// Sets the present mode of the surface
+/// Falls back to AutoVsync if the mode is not supported by the adapter
+pub fn set_present_mode(ctx: &mut Context, present_mode: PresentMode) {
+    ctx.render.reconfigure_present_mode(present_mode);
+}
+
+/// Limits the frame rate by sleeping the remainder of each frame
+/// None disables the limiter
+pub fn set_target_fps(ctx: &mut Context, fps: Option<u32>) {
+    assert!(fps != Some(0), "target fps must be greater than 0");
+    ctx.time.target_fps = fps;
+}
+
+/// Enables/Disables skipping updates and rendering while the window is unfocused
+/// Enabled by default, disable to keep running in the background e.g. when recording
+pub fn set_pause_on_unfocus(ctx: &mut Context, enabled: bool) {
+    if ctx.paused() && !enabled {
+        ctx.time.skip_time();
+    }
+    ctx.pause_on_unfocus = enabled;
+}
+
+/// Sets the title of the window
+pub fn set_title(ctx: &mut Context, title: &str) {
+    if let Some(window) = &ctx.render.window {
+        window.set_title(title);
+    }
+}
+
+/// Sets the icon of the window from 8 bit rgba pixels in row-major order
+/// Returns an error if rgba does not contain width * height pixels
+pub fn set_icon(
+    ctx: &mut Context,
+    rgba: &[u8],
+    width: u32,
+    height: u32,
+) -> Result<(), RaymarcherError> {
+    let icon = Icon::from_rgba(rgba.to_vec(), width, height)?;
+    if let Some(window) = &ctx.render.window {
+        window.set_window_icon(Some(icon));
+    }
+    Ok(())
+}
+
 /// Enables/Disables borderless windowed mode
 pub fn set_fullscreen(ctx: &mut Context, fullscreen: bool) {
     let fullscreen_mode = if fullscreen {
@@ -22,30 +66,60 @@ pub fn set_fullscreen(ctx: &mut Context, fullscreen: bool) {
     } else {
         None
     };
-    ctx.render.window.set_fullscreen(fullscreen_mode);
+    if let Some(window) = &ctx.render.window {
+        window.set_fullscreen(fullscreen_mode);
+    }
 }
 
 /// Enables/Disables window resizing
 pub fn set_resizeable(ctx: &mut Context, resizable: bool) {
-    ctx.render.window.set_resizable(resizable);
+    if let Some(window) = &ctx.render.window {
+        window.set_resizable(resizable);
+    }
+}
+
+/// Returns the (width, height) inner size of the window in physical pixels
+/// Returns the render resolution when running headless
+pub fn size(ctx: &Context) -> (u32, u32) {
+    let size = ctx.render.window_size;
+    (size.width, size.height)
 }
 
 /// Sets the inner size of the window
 pub fn set_size(ctx: &mut Context, size: (u32, u32)) {
-    ctx.render
-        .window
-        .set_inner_size(PhysicalSize::new(size.0, size.1));
+    if let Some(window) = &ctx.render.window {
+        window.set_inner_size(PhysicalSize::new(size.0, size.1));
+    }
 }
 
 /// Enables/Disables the cursor
 /// If disabled: Turns off cursor and locks cursor to middle of window
 pub fn set_cursor_enabled(ctx: &mut Context, enabled: bool) {
-    ctx.render.window.set_cursor_visible(enabled);
+    if let Some(window) = &ctx.render.window {
+        window.set_cursor_visible(enabled);
+    }
     let grab_mode = if enabled {
         CursorGrabMode::None
     } else {
         CursorGrabMode::Locked
     };
-    // TODO handle error
-    ctx.render.window.set_cursor_grab(grab_mode).unwrap();
+    if let Err(err) = set_cursor_grab(ctx, grab_mode) {
+        log::warn!("{err}");
+    }
+}
+
+/// Keeps the cursor inside the window (Confined), in place (Locked) or free (None)
+/// Falls back to Confined on platforms which do not support Locked
+/// Does not change cursor visibility, see set_cursor_enabled
+pub fn set_cursor_grab(ctx: &mut Context, mode: CursorGrabMode) -> Result<(), RaymarcherError> {
+    let Some(window) = &ctx.render.window else {
+        return Ok(());
+    };
+    window
+        .set_cursor_grab(mode)
+        .or_else(|err| match mode {
+            CursorGrabMode::Locked => window.set_cursor_grab(CursorGrabMode::Confined),
+            _ => Err(err),
+        })
+        .map_err(RaymarcherError::CursorGrabFailed)
 }
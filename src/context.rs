@@ -1,4 +1,4 @@
-use crate::{input::InputContext, render::RenderContext, time::TimeContext};
+use crate::{input::InputContext, recording::Recording, render::RenderContext, time::TimeContext};
 
 /// Holds all the neccesary state for running the engine
 /// Should be sent with each command
@@ -6,4 +6,62 @@ pub struct Context {
     pub(crate) render: RenderContext,
     pub(crate) time: TimeContext,
     pub(crate) input: InputContext,
+    // Window focus, updates and rendering are skipped while unfocused if pause_on_unfocus
+    pub(crate) focused: bool,
+    pub(crate) pause_on_unfocus: bool,
+    // Minimized or fully hidden window, there is no surface worth rendering to
+    pub(crate) minimized: bool,
+    // Occluded windows are still resized, so both signals are tracked separately
+    pub(crate) occluded: bool,
+    // Active recording, frames are written after each render
+    pub(crate) recording: Option<Recording>,
+}
+
+impl Context {
+    pub(crate) fn new(render: RenderContext, time: TimeContext, input: InputContext) -> Self {
+        Self {
+            render,
+            time,
+            input,
+            focused: true,
+            pause_on_unfocus: true,
+            minimized: false,
+            occluded: false,
+            recording: None,
+        }
+    }
+
+    /// Returns true if updates and rendering should be skipped
+    pub(crate) fn paused(&self) -> bool {
+        self.pause_on_unfocus && !self.focused
+    }
+
+    /// Returns true if the window can not be seen and rendering should be skipped
+    /// Updates keep running unless paused
+    pub(crate) fn hidden(&self) -> bool {
+        self.minimized || self.occluded
+    }
+
+    /// Writes the rendered frame if recording
+    /// Stops recording once all frames are written or writing fails
+    pub(crate) fn record_frame(&mut self) {
+        let Some(recording) = &mut self.recording else {
+            return;
+        };
+        match recording.save_frame(&self.render) {
+            Ok(false) => {}
+            Ok(true) => self.stop_recording(),
+            Err(err) => {
+                log::error!("stopped recording: {err}");
+                self.stop_recording();
+            }
+        }
+    }
+
+    /// Stops recording and restores the timestep used before it started
+    pub(crate) fn stop_recording(&mut self) {
+        if let Some(recording) = self.recording.take() {
+            self.time.fixed_dt = recording.prev_fixed_dt;
+        }
+    }
 }
@@ -0,0 +1,113 @@
+use std::fmt;
+
+use crate::render::MAX_SHAPE_DEPTH;
+
+/// Errors which can occur while initializing the raymarcher or loading resources
+#[derive(Debug)]
+pub enum RaymarcherError {
+    /// No adapter compatible with the surface was found
+    AdapterNotFound,
+    /// The adapter could not provide a device
+    DeviceRequestFailed(wgpu::RequestDeviceError),
+    /// The platform could not create the window
+    WindowCreationFailed(winit::error::OsError),
+    /// A surface could not be created for the window
+    SurfaceCreationFailed(wgpu::CreateSurfaceError),
+    /// The compute shader failed to compile, e.g. because of an invalid custom sdf
+    ShaderCompilationFailed(wgpu::Error),
+    /// A texture could not be read or decoded as png
+    TextureLoadFailed(png::DecodingError),
+    /// The pixels of a window icon do not match its size
+    InvalidIcon(winit::window::BadIcon),
+    /// The platform does not support the cursor grab mode
+    CursorGrabFailed(winit::error::ExternalError),
+    /// A shape is nested deeper than MAX_SHAPE_DEPTH, contains the depth of the shape
+    ShapeTooDeep(usize),
+    /// The recording directory or a frame could not be written
+    RecordingFailed(png::EncodingError),
+    /// RunConfig::workgroup_size is empty or exceeds the limits of the device
+    InvalidWorkgroupSize((u32, u32)),
+    /// A texture is empty or larger than the device supports, contains the size of the texture
+    InvalidTextureSize((u32, u32)),
+}
+
+impl fmt::Display for RaymarcherError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RaymarcherError::AdapterNotFound => write!(f, "no compatible adapter found"),
+            RaymarcherError::DeviceRequestFailed(err) => {
+                write!(f, "failed to request device: {err}")
+            }
+            RaymarcherError::WindowCreationFailed(err) => {
+                write!(f, "failed to create window: {err}")
+            }
+            RaymarcherError::SurfaceCreationFailed(err) => {
+                write!(f, "failed to create surface: {err}")
+            }
+            RaymarcherError::ShaderCompilationFailed(err) => {
+                write!(f, "failed to compile shader: {err}")
+            }
+            RaymarcherError::TextureLoadFailed(err) => {
+                write!(f, "failed to load texture: {err}")
+            }
+            RaymarcherError::InvalidIcon(err) => write!(f, "invalid icon: {err}"),
+            RaymarcherError::CursorGrabFailed(err) => {
+                write!(f, "failed to grab cursor: {err}")
+            }
+            RaymarcherError::ShapeTooDeep(depth) => write!(
+                f,
+                "shape is nested {depth} levels deep, at most {MAX_SHAPE_DEPTH} are supported"
+            ),
+            RaymarcherError::RecordingFailed(err) => write!(f, "failed to record: {err}"),
+            RaymarcherError::InvalidWorkgroupSize(size) => {
+                write!(f, "workgroup size {size:?} is empty or too large")
+            }
+            RaymarcherError::InvalidTextureSize(size) => {
+                write!(f, "texture size {size:?} is empty or too large")
+            }
+        }
+    }
+}
+
+impl std::error::Error for RaymarcherError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RaymarcherError::AdapterNotFound => None,
+            RaymarcherError::DeviceRequestFailed(err) => Some(err),
+            RaymarcherError::WindowCreationFailed(err) => Some(err),
+            RaymarcherError::SurfaceCreationFailed(err) => Some(err),
+            RaymarcherError::ShaderCompilationFailed(err) => Some(err),
+            RaymarcherError::TextureLoadFailed(err) => Some(err),
+            RaymarcherError::InvalidIcon(err) => Some(err),
+            RaymarcherError::CursorGrabFailed(err) => Some(err),
+            RaymarcherError::ShapeTooDeep(_) => None,
+            RaymarcherError::RecordingFailed(err) => Some(err),
+            RaymarcherError::InvalidWorkgroupSize(_) => None,
+            RaymarcherError::InvalidTextureSize(_) => None,
+        }
+    }
+}
+
+impl From<wgpu::RequestDeviceError> for RaymarcherError {
+    fn from(err: wgpu::RequestDeviceError) -> Self {
+        RaymarcherError::DeviceRequestFailed(err)
+    }
+}
+
+impl From<wgpu::CreateSurfaceError> for RaymarcherError {
+    fn from(err: wgpu::CreateSurfaceError) -> Self {
+        RaymarcherError::SurfaceCreationFailed(err)
+    }
+}
+
+impl From<png::DecodingError> for RaymarcherError {
+    fn from(err: png::DecodingError) -> Self {
+        RaymarcherError::TextureLoadFailed(err)
+    }
+}
+
+impl From<winit::window::BadIcon> for RaymarcherError {
+    fn from(err: winit::window::BadIcon) -> Self {
+        RaymarcherError::InvalidIcon(err)
+    }
+}
@@ -1,17 +1,130 @@
 pub use winit::event::VirtualKeyCode as KeyCode;
 use winit::event::{ModifiersState, MouseButton};
 
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 
 use crate::render::RenderContext;
 
+// Default max seconds between two clicks of a double click
+const DOUBLE_CLICK_TIME: f32 = 0.3;
+
 #[derive(Default)]
 pub struct InputContext {
     pub keyboard: KeyboardContext,
     pub mouse: MouseContext,
+    pub gamepad: GamepadContext,
+    // Inputs bound to each user defined action
+    actions: HashMap<String, Vec<InputBinding>>,
+    // Events received since the last frame in order, cleared but not freed each frame
+    events: Vec<InputEvent>,
+}
+
+/// Input received from the window, recorded in order for each frame
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum InputEvent {
+    /// Also sent for repeats while the key is held
+    KeyPressed(KeyCode),
+    KeyReleased(KeyCode),
+    MouseButtonPressed(MouseButton),
+    MouseButtonReleased(MouseButton),
+    /// New cursor position in physical pixels
+    MouseMoved((f64, f64)),
+    /// Scroll amount of a single wheel event
+    MouseScrolled((f64, f64)),
+}
+
+/// Key or mouse button which can be bound to an action
+#[derive(Debug, Hash, PartialEq, Eq, Clone, Copy)]
+pub enum InputBinding {
+    Key(KeyCode),
+    Mouse(MouseButton),
+}
+
+impl From<KeyCode> for InputBinding {
+    fn from(keycode: KeyCode) -> Self {
+        Self::Key(keycode)
+    }
+}
+
+impl From<MouseButton> for InputBinding {
+    fn from(button: MouseButton) -> Self {
+        Self::Mouse(button)
+    }
+}
+
+// Actions
+impl InputContext {
+    /// Binds action to bindings, replacing any previous bindings
+    pub fn bind_action(&mut self, action: &str, bindings: &[InputBinding]) {
+        self.actions.insert(action.to_string(), bindings.to_vec());
+    }
+
+    /// Returns true if any input bound to action is down
+    /// Accepts repeating
+    pub fn action_pressed(&self, action: &str) -> bool {
+        self.bindings(action)
+            .iter()
+            .any(|binding| self.binding_state(binding).0)
+    }
+
+    /// Returns true if action was pressed this frame
+    /// Holding one bound input while pressing another does not count
+    pub fn action_just_pressed(&self, action: &str) -> bool {
+        let bindings = self.bindings(action);
+        bindings.iter().any(|binding| self.binding_state(binding).0)
+            && !bindings.iter().any(|binding| self.binding_state(binding).1)
+    }
+
+    /// Returns true if the last input bound to action was released this frame
+    pub fn action_released(&self, action: &str) -> bool {
+        let bindings = self.bindings(action);
+        !bindings.iter().any(|binding| self.binding_state(binding).0)
+            && bindings.iter().any(|binding| self.binding_state(binding).1)
+    }
+
+    fn bindings(&self, action: &str) -> &[InputBinding] {
+        self.actions.get(action).map_or(&[], |bindings| bindings)
+    }
+
+    // Returns if binding is pressed this frame and the previous frame
+    fn binding_state(&self, binding: &InputBinding) -> (bool, bool) {
+        match binding {
+            InputBinding::Key(keycode) => (
+                self.keyboard.pressed.contains(keycode),
+                self.keyboard.previous_pressed.contains(keycode),
+            ),
+            InputBinding::Mouse(button) => (
+                self.mouse.pressed.contains(button),
+                self.mouse.previous_pressed.contains(button),
+            ),
+        }
+    }
+}
+
+impl InputContext {
+    /// Sets the time in seconds used to timestamp presses
+    /// Should be called each frame
+    pub(crate) fn set_time(&mut self, time: f32) {
+        self.keyboard.time = time;
+        self.mouse.time = time;
+    }
+
+    /// Returns the events received since the last frame in order
+    pub fn events(&self) -> &[InputEvent] {
+        &self.events
+    }
+
+    pub(crate) fn push_event(&mut self, event: InputEvent) {
+        self.events.push(event);
+    }
+
+    /// Clears the events of the current frame
+    /// Should be called each frame
+    pub(crate) fn clear_events(&mut self) {
+        self.events.clear();
+    }
 }
 
-#[derive(Default)]
 pub struct MouseContext {
     on_screen: bool,
     pos: (f64, f64),
@@ -19,6 +132,37 @@ pub struct MouseContext {
     pressed: HashSet<MouseButton>,
     previous_pressed: HashSet<MouseButton>,
     scroll_delta: (f64, f64),
+    // Sum of all scroll events since start or the last reset
+    scroll_total: (f64, f64),
+    // Time of the current frame and the time each held button was first pressed
+    time: f32,
+    press_times: HashMap<MouseButton, f32>,
+    // Mouse position when each held button was pressed
+    press_positions: HashMap<MouseButton, (f64, f64)>,
+    // Time of the last click not yet part of a double click
+    last_click_times: HashMap<MouseButton, f32>,
+    double_clicked: HashSet<MouseButton>,
+    pub(crate) double_click_time: f32,
+}
+
+impl Default for MouseContext {
+    fn default() -> Self {
+        Self {
+            on_screen: false,
+            pos: (0.0, 0.0),
+            mouse_delta: (0.0, 0.0),
+            pressed: HashSet::new(),
+            previous_pressed: HashSet::new(),
+            scroll_delta: (0.0, 0.0),
+            scroll_total: (0.0, 0.0),
+            time: 0.0,
+            press_times: HashMap::new(),
+            press_positions: HashMap::new(),
+            last_click_times: HashMap::new(),
+            double_clicked: HashSet::new(),
+            double_click_time: DOUBLE_CLICK_TIME,
+        }
+    }
 }
 
 impl MouseContext {
@@ -39,6 +183,36 @@ impl MouseContext {
         !self.pressed.contains(&keycode) && self.previous_pressed.contains(&keycode)
     }
 
+    /// Returns the seconds since MouseButton was first pressed
+    /// Returns 0 if MouseButton is not pressed
+    pub fn button_held_duration(&self, keycode: MouseButton) -> f32 {
+        self.press_times
+            .get(&keycode)
+            .map_or(0.0, |press_time| self.time - press_time)
+    }
+
+    /// Returns true if MouseButton was pressed this frame within
+    /// double_click_time of the previous press
+    /// A third click starts a new double click instead of completing another
+    pub fn button_double_clicked(&self, keycode: MouseButton) -> bool {
+        self.double_clicked.contains(&keycode)
+    }
+
+    /// Returns the (dx, dy) motion since MouseButton was pressed
+    /// Returns None if MouseButton is not pressed
+    pub fn drag_delta(&self, keycode: MouseButton) -> Option<(f64, f64)> {
+        self.press_positions
+            .get(&keycode)
+            .map(|origin| (self.pos.0 - origin.0, self.pos.1 - origin.1))
+    }
+
+    /// Returns true if MouseButton is pressed and the mouse has moved
+    /// more than threshold physical pixels since the press
+    pub fn is_dragging(&self, keycode: MouseButton, threshold: f64) -> bool {
+        self.drag_delta(keycode)
+            .is_some_and(|(dx, dy)| dx * dx + dy * dy > threshold * threshold)
+    }
+
     /// Returns if mouse is on screen or not
     pub fn on_screen(&self) -> bool {
         self.on_screen
@@ -51,13 +225,8 @@ impl MouseContext {
 
     /// Returns the current pixel under the mouse
     pub fn mouse_pos_pixel(&self, ctx: &RenderContext) -> (u32, u32) {
-        // When holding the mouse button down pos can get bigger than physical size
-        // So clamp to avoid out of bounds
-        let relative_x = self.pos.0 / ctx.window_size.width as f64;
-        let relative_y = self.pos.1 / ctx.window_size.height as f64;
-        let pixel_x = relative_x * ctx.resolution.0 as f64;
-        let pixel_y = relative_y * ctx.resolution.1 as f64;
-        (pixel_x as u32, pixel_y as u32)
+        let window_size = (ctx.window_size.width, ctx.window_size.height);
+        physical_to_pixel(self.pos, window_size, ctx.resolution)
     }
 
     /// Returns the (dx, dy) change in mouse position
@@ -69,6 +238,15 @@ impl MouseContext {
         self.scroll_delta
     }
 
+    /// Returns the sum of all scroll deltas since start or the last reset_scroll_total
+    pub fn scroll_total(&self) -> (f64, f64) {
+        self.scroll_total
+    }
+
+    pub(crate) fn reset_scroll_total(&mut self) {
+        self.scroll_total = (0.0, 0.0);
+    }
+
     /// Sets mouse off screen
     pub(crate) fn set_on_screen(&mut self, on_screen: bool) {
         self.on_screen = on_screen;
@@ -80,15 +258,10 @@ impl MouseContext {
 
         // Check if mouse is on screen
         // When holding mouse button CursorLeft event will not be called so need check here
-        if x >= 0.0
+        self.on_screen = x >= 0.0
             && x < ctx.window_size.width as f64
             && y >= 0.0
-            && y < ctx.window_size.height as f64
-        {
-            self.on_screen = true;
-        } else {
-            self.on_screen = false;
-        }
+            && y < ctx.window_size.height as f64;
     }
 
     /// Sets the (dx, dy) change in mouse position
@@ -98,22 +271,57 @@ impl MouseContext {
 
     pub(crate) fn set_scroll_delta(&mut self, change: (f64, f64)) {
         self.scroll_delta = change;
+        self.scroll_total.0 += change.0;
+        self.scroll_total.1 += change.1;
+    }
+
+    /// Resets the mouse delta
+    /// Should be called each frame
+    pub(crate) fn reset_mouse_delta(&mut self) {
+        self.mouse_delta = (0.0, 0.0);
+    }
+
+    /// Resets the scroll delta
+    /// Should be called each frame
+    pub(crate) fn reset_scroll_delta(&mut self) {
+        self.scroll_delta = (0.0, 0.0);
     }
 
     /// Sets button for current frame
     pub(crate) fn press_button(&mut self, keycode: MouseButton) {
+        if !self.pressed.contains(&keycode) {
+            self.register_click(keycode);
+        }
         self.pressed.insert(keycode);
+        self.press_times.entry(keycode).or_insert(self.time);
+        self.press_positions.entry(keycode).or_insert(self.pos);
     }
 
     /// Release button
     pub(crate) fn release_button(&mut self, keycode: MouseButton) {
         self.pressed.remove(&keycode);
+        self.press_times.remove(&keycode);
+        self.press_positions.remove(&keycode);
+    }
+
+    // Completes a double click if the previous click was recent enough
+    // Otherwise remembers the click as the start of one
+    fn register_click(&mut self, keycode: MouseButton) {
+        match self.last_click_times.remove(&keycode) {
+            Some(last) if self.time - last <= self.double_click_time => {
+                self.double_clicked.insert(keycode);
+            }
+            _ => {
+                self.last_click_times.insert(keycode, self.time);
+            }
+        }
     }
 
     /// Save current buttons in previous
     /// Should be called each frame
     pub(crate) fn save_buttons(&mut self) {
-        self.previous_pressed = self.pressed.clone()
+        self.previous_pressed = self.pressed.clone();
+        self.double_clicked.clear();
     }
 }
 
@@ -123,6 +331,9 @@ pub struct KeyboardContext {
     previous_pressed: HashSet<KeyCode>,
     pressed_modifiers: HashSet<KeyModifier>,
     previous_pressed_modifiers: HashSet<KeyModifier>,
+    // Time of the current frame and the time each held key was first pressed
+    time: f32,
+    press_times: HashMap<KeyCode, f32>,
 }
 
 #[derive(Debug, Hash, Ord, PartialOrd, PartialEq, Eq, Clone, Copy)]
@@ -152,6 +363,14 @@ impl KeyboardContext {
         !self.pressed.contains(&keycode) && self.previous_pressed.contains(&keycode)
     }
 
+    /// Returns the seconds since KeyCode was first pressed
+    /// Returns 0 if KeyCode is not pressed
+    pub fn key_held_duration(&self, keycode: KeyCode) -> f32 {
+        self.press_times
+            .get(&keycode)
+            .map_or(0.0, |press_time| self.time - press_time)
+    }
+
     pub fn modifier_pressed(&self, modifier: KeyModifier) -> bool {
         self.pressed_modifiers.contains(&modifier)
     }
@@ -171,11 +390,14 @@ impl KeyboardContext {
     /// Sets key for current frame
     pub(crate) fn set_key(&mut self, keycode: KeyCode) {
         self.pressed.insert(keycode);
+        // Repeated presses keep the original timestamp
+        self.press_times.entry(keycode).or_insert(self.time);
     }
 
     /// Release key
     pub(crate) fn release_key(&mut self, keycode: KeyCode) {
         self.pressed.remove(&keycode);
+        self.press_times.remove(&keycode);
     }
 
     pub fn modifiers_changed(&mut self, state: ModifiersState) {
@@ -205,13 +427,295 @@ impl KeyboardContext {
     }
 }
 
+/// Index of a connected gamepad
+pub type GamepadId = usize;
+
+#[derive(Debug, Hash, PartialEq, Eq, Clone, Copy)]
+pub enum GamepadButton {
+    South,
+    East,
+    North,
+    West,
+    LeftTrigger,
+    LeftTrigger2,
+    RightTrigger,
+    RightTrigger2,
+    Select,
+    Start,
+    Mode,
+    LeftThumb,
+    RightThumb,
+    DPadUp,
+    DPadDown,
+    DPadLeft,
+    DPadRight,
+}
+
+#[derive(Debug, Hash, PartialEq, Eq, Clone, Copy)]
+pub enum GamepadAxis {
+    LeftStickX,
+    LeftStickY,
+    RightStickX,
+    RightStickY,
+    LeftZ,
+    RightZ,
+}
+
+// Default axis values below which sticks are considered centered
+const GAMEPAD_DEADZONE: f32 = 0.1;
+
+/// State of all connected gamepads
+/// All queries return released/centered for gamepads that are not connected
+pub struct GamepadContext {
+    connected: HashSet<GamepadId>,
+    // Connection changes since the last frame
+    just_connected: Vec<GamepadId>,
+    just_disconnected: Vec<GamepadId>,
+    pressed: HashSet<(GamepadId, GamepadButton)>,
+    previous_pressed: HashSet<(GamepadId, GamepadButton)>,
+    axes: HashMap<(GamepadId, GamepadAxis), f32>,
+    pub(crate) deadzone: f32,
+    // Source of gamepad events, None if gilrs failed to initialize
+    #[cfg(feature = "gamepad")]
+    gilrs: Option<gilrs::Gilrs>,
+}
+
+impl Default for GamepadContext {
+    fn default() -> Self {
+        #[allow(unused_mut)]
+        let mut gamepad = Self {
+            connected: HashSet::new(),
+            just_connected: Vec::new(),
+            just_disconnected: Vec::new(),
+            pressed: HashSet::new(),
+            previous_pressed: HashSet::new(),
+            axes: HashMap::new(),
+            deadzone: GAMEPAD_DEADZONE,
+            #[cfg(feature = "gamepad")]
+            gilrs: init_gilrs(),
+        };
+        // Gamepads plugged in before startup do not send connection events
+        #[cfg(feature = "gamepad")]
+        if let Some(gilrs) = &gamepad.gilrs {
+            let ids: Vec<GamepadId> = gilrs.gamepads().map(|(id, _)| id.into()).collect();
+            for id in ids {
+                gamepad.connect(id);
+            }
+        }
+        gamepad
+    }
+}
+
+// Getting gamepad state
+impl GamepadContext {
+    /// Returns the ids of all connected gamepads in ascending order
+    pub fn gamepads(&self) -> Vec<GamepadId> {
+        let mut ids: Vec<GamepadId> = self.connected.iter().copied().collect();
+        ids.sort_unstable();
+        ids
+    }
+
+    /// Returns the ids of gamepads connected this frame
+    pub fn just_connected(&self) -> &[GamepadId] {
+        &self.just_connected
+    }
+
+    /// Returns the ids of gamepads disconnected this frame
+    pub fn just_disconnected(&self) -> &[GamepadId] {
+        &self.just_disconnected
+    }
+
+    /// Returns true if GamepadButton is down
+    /// Accepts repeating
+    pub fn button_pressed(&self, id: GamepadId, button: GamepadButton) -> bool {
+        self.pressed.contains(&(id, button))
+    }
+
+    /// Returns true if GamepadButton was pressed this frame
+    pub fn button_just_pressed(&self, id: GamepadId, button: GamepadButton) -> bool {
+        self.pressed.contains(&(id, button)) && !self.previous_pressed.contains(&(id, button))
+    }
+
+    /// Returns true if GamepadButton was released this frame
+    pub fn button_released(&self, id: GamepadId, button: GamepadButton) -> bool {
+        !self.pressed.contains(&(id, button)) && self.previous_pressed.contains(&(id, button))
+    }
+
+    /// Returns the value of GamepadAxis in [-1, 1]
+    /// Values within the deadzone are returned as 0
+    pub fn axis_value(&self, id: GamepadId, axis: GamepadAxis) -> f32 {
+        let value = self.axes.get(&(id, axis)).copied().unwrap_or(0.0);
+        if value.abs() < self.deadzone {
+            0.0
+        } else {
+            value
+        }
+    }
+}
+
+// Fed by gilrs each frame, nothing connects gamepads without the gamepad feature
+#[cfg_attr(not(feature = "gamepad"), allow(dead_code))]
+impl GamepadContext {
+    pub(crate) fn connect(&mut self, id: GamepadId) {
+        if self.connected.insert(id) {
+            self.just_connected.push(id);
+        }
+    }
+
+    /// Removes gamepad and releases all of its buttons and axes
+    pub(crate) fn disconnect(&mut self, id: GamepadId) {
+        if self.connected.remove(&id) {
+            self.just_disconnected.push(id);
+        }
+        self.pressed.retain(|(pad, _)| *pad != id);
+        self.axes.retain(|(pad, _), _| *pad != id);
+    }
+
+    pub(crate) fn press_button(&mut self, id: GamepadId, button: GamepadButton) {
+        self.pressed.insert((id, button));
+    }
+
+    pub(crate) fn release_button(&mut self, id: GamepadId, button: GamepadButton) {
+        self.pressed.remove(&(id, button));
+    }
+
+    pub(crate) fn set_axis(&mut self, id: GamepadId, axis: GamepadAxis, value: f32) {
+        self.axes.insert((id, axis), value.clamp(-1.0, 1.0));
+    }
+
+    /// Save current buttons in previous and clears connection changes
+    /// Should be called each frame
+    pub(crate) fn save_buttons(&mut self) {
+        self.previous_pressed = self.pressed.clone();
+        self.just_connected.clear();
+        self.just_disconnected.clear();
+    }
+}
+
+#[cfg(feature = "gamepad")]
+impl GamepadContext {
+    /// Applies the gamepad events received since the last poll
+    /// Should be called each frame before the update callback
+    pub(crate) fn poll(&mut self) {
+        let Some(mut gilrs) = self.gilrs.take() else {
+            return;
+        };
+        while let Some(gilrs::Event { id, event, .. }) = gilrs.next_event() {
+            let id = GamepadId::from(id);
+            match event {
+                gilrs::EventType::Connected => self.connect(id),
+                gilrs::EventType::Disconnected => self.disconnect(id),
+                gilrs::EventType::ButtonPressed(button, _) => {
+                    if let Some(button) = gilrs_button(button) {
+                        self.press_button(id, button);
+                    }
+                }
+                gilrs::EventType::ButtonReleased(button, _) => {
+                    if let Some(button) = gilrs_button(button) {
+                        self.release_button(id, button);
+                    }
+                }
+                gilrs::EventType::AxisChanged(axis, value, _) => {
+                    if let Some(axis) = gilrs_axis(axis) {
+                        self.set_axis(id, axis, value);
+                    }
+                }
+                _ => {}
+            }
+        }
+        self.gilrs = Some(gilrs);
+    }
+}
+
+#[cfg(feature = "gamepad")]
+fn init_gilrs() -> Option<gilrs::Gilrs> {
+    match gilrs::Gilrs::new() {
+        Ok(gilrs) => Some(gilrs),
+        // Unsupported platforms get a backend which never reports gamepads
+        Err(gilrs::Error::NotImplemented(gilrs)) => Some(gilrs),
+        Err(err) => {
+            log::warn!("gamepads are unavailable: {err}");
+            None
+        }
+    }
+}
+
+/// Returns None for buttons without a GamepadButton
+#[cfg(feature = "gamepad")]
+fn gilrs_button(button: gilrs::Button) -> Option<GamepadButton> {
+    use gilrs::Button;
+    Some(match button {
+        Button::South => GamepadButton::South,
+        Button::East => GamepadButton::East,
+        Button::North => GamepadButton::North,
+        Button::West => GamepadButton::West,
+        Button::LeftTrigger => GamepadButton::LeftTrigger,
+        Button::LeftTrigger2 => GamepadButton::LeftTrigger2,
+        Button::RightTrigger => GamepadButton::RightTrigger,
+        Button::RightTrigger2 => GamepadButton::RightTrigger2,
+        Button::Select => GamepadButton::Select,
+        Button::Start => GamepadButton::Start,
+        Button::Mode => GamepadButton::Mode,
+        Button::LeftThumb => GamepadButton::LeftThumb,
+        Button::RightThumb => GamepadButton::RightThumb,
+        Button::DPadUp => GamepadButton::DPadUp,
+        Button::DPadDown => GamepadButton::DPadDown,
+        Button::DPadLeft => GamepadButton::DPadLeft,
+        Button::DPadRight => GamepadButton::DPadRight,
+        _ => return None,
+    })
+}
+
+/// Returns None for axes without a GamepadAxis, the dpad is reported as buttons
+#[cfg(feature = "gamepad")]
+fn gilrs_axis(axis: gilrs::Axis) -> Option<GamepadAxis> {
+    use gilrs::Axis;
+    Some(match axis {
+        Axis::LeftStickX => GamepadAxis::LeftStickX,
+        Axis::LeftStickY => GamepadAxis::LeftStickY,
+        Axis::RightStickX => GamepadAxis::RightStickX,
+        Axis::RightStickY => GamepadAxis::RightStickY,
+        Axis::LeftZ => GamepadAxis::LeftZ,
+        Axis::RightZ => GamepadAxis::RightZ,
+        _ => return None,
+    })
+}
+
+/// Maps a physical window position to the render pixel below it
+fn physical_to_pixel(
+    pos: (f64, f64),
+    window_size: (u32, u32),
+    resolution: (u32, u32),
+) -> (u32, u32) {
+    // When holding the mouse button down pos can get bigger than physical size or negative
+    // So clamp to avoid out of bounds
+    let axis = |pos: f64, window: u32, resolution: u32| {
+        let pixel = pos / window as f64 * resolution as f64;
+        (pixel.max(0.0) as u32).min(resolution.saturating_sub(1))
+    };
+    (
+        axis(pos.0, window_size.0, resolution.0),
+        axis(pos.1, window_size.1, resolution.1),
+    )
+}
+
 #[cfg(test)]
 mod tests {
     use winit::event::ModifiersState;
 
+    use winit::event::MouseButton;
+
+    use crate::input::physical_to_pixel;
+    use crate::input::GamepadAxis;
+    use crate::input::GamepadButton;
+    use crate::input::GamepadContext;
+    use crate::input::InputBinding;
+    use crate::input::InputContext;
+    use crate::input::InputEvent;
     use crate::input::KeyCode;
     use crate::input::KeyModifier;
     use crate::input::KeyboardContext;
+    use crate::input::MouseContext;
 
     #[test]
     fn key_pressed_test() {
@@ -261,6 +765,136 @@ mod tests {
         assert!(kc.key_released(KeyCode::A));
     }
 
+    #[test]
+    fn key_held_duration_test() {
+        let mut ic = InputContext::default();
+        ic.set_time(1.0);
+        ic.keyboard.set_key(KeyCode::A);
+        ic.mouse.press_button(MouseButton::Left);
+
+        assert_eq!(ic.keyboard.key_held_duration(KeyCode::A), 0.0);
+        assert_eq!(ic.keyboard.key_held_duration(KeyCode::B), 0.0);
+
+        // Repeats do not restart the duration
+        ic.set_time(2.5);
+        ic.keyboard.set_key(KeyCode::A);
+        assert_eq!(ic.keyboard.key_held_duration(KeyCode::A), 1.5);
+        assert_eq!(ic.mouse.button_held_duration(MouseButton::Left), 1.5);
+
+        ic.keyboard.release_key(KeyCode::A);
+        ic.mouse.release_button(MouseButton::Left);
+        assert_eq!(ic.keyboard.key_held_duration(KeyCode::A), 0.0);
+        assert_eq!(ic.mouse.button_held_duration(MouseButton::Left), 0.0);
+    }
+
+    #[test]
+    fn action_test() {
+        let mut ic = InputContext::default();
+        ic.bind_action(
+            "jump",
+            &[
+                KeyCode::Space.into(),
+                InputBinding::Mouse(MouseButton::Left),
+            ],
+        );
+
+        assert!(!ic.action_pressed("jump"));
+        assert!(!ic.action_pressed("unbound"));
+
+        ic.mouse.press_button(MouseButton::Left);
+        assert!(ic.action_pressed("jump"));
+        assert!(ic.action_just_pressed("jump"));
+
+        // Second binding while the first is held
+        ic.keyboard.save_keys();
+        ic.mouse.save_buttons();
+        ic.keyboard.set_key(KeyCode::Space);
+        assert!(ic.action_pressed("jump"));
+        assert!(!ic.action_just_pressed("jump"));
+
+        // Released once no binding is held
+        ic.keyboard.save_keys();
+        ic.mouse.save_buttons();
+        ic.mouse.release_button(MouseButton::Left);
+        assert!(!ic.action_released("jump"));
+        ic.keyboard.save_keys();
+        ic.mouse.save_buttons();
+        ic.keyboard.release_key(KeyCode::Space);
+        assert!(ic.action_released("jump"));
+    }
+
+    #[test]
+    fn drag_test() {
+        let mut mc = MouseContext {
+            pos: (10.0, 10.0),
+            ..Default::default()
+        };
+        assert_eq!(mc.drag_delta(MouseButton::Right), None);
+
+        mc.press_button(MouseButton::Right);
+        assert_eq!(mc.drag_delta(MouseButton::Right), Some((0.0, 0.0)));
+
+        mc.pos = (13.0, 14.0);
+        assert_eq!(mc.drag_delta(MouseButton::Right), Some((3.0, 4.0)));
+        assert!(mc.is_dragging(MouseButton::Right, 4.0));
+        assert!(!mc.is_dragging(MouseButton::Right, 5.0));
+        assert!(!mc.is_dragging(MouseButton::Left, 0.0));
+
+        mc.release_button(MouseButton::Right);
+        assert_eq!(mc.drag_delta(MouseButton::Right), None);
+    }
+
+    #[test]
+    fn double_click_test() {
+        // Clicks in a new frame at time
+        fn click(mc: &mut MouseContext, time: f32) -> bool {
+            mc.save_buttons();
+            mc.time = time;
+            mc.press_button(MouseButton::Left);
+            mc.release_button(MouseButton::Left);
+            mc.button_double_clicked(MouseButton::Left)
+        }
+        let mut mc = MouseContext::default();
+
+        assert!(!click(&mut mc, 0.0));
+        assert!(click(&mut mc, 0.2));
+        // Third click starts over
+        assert!(!click(&mut mc, 0.4));
+        assert!(click(&mut mc, 0.5));
+        // Too slow
+        assert!(!click(&mut mc, 1.0));
+        assert!(!click(&mut mc, 1.5));
+    }
+
+    #[test]
+    fn gamepad_test() {
+        let mut gc = GamepadContext::default();
+
+        // No gamepads connected
+        assert!(gc.gamepads().is_empty());
+        assert!(!gc.button_pressed(0, GamepadButton::South));
+        assert_eq!(gc.axis_value(0, GamepadAxis::LeftStickX), 0.0);
+
+        gc.connect(0);
+        gc.press_button(0, GamepadButton::South);
+        gc.set_axis(0, GamepadAxis::LeftStickX, 0.5);
+        gc.set_axis(0, GamepadAxis::LeftStickY, 0.05);
+        assert_eq!(gc.just_connected(), [0]);
+        assert!(gc.button_just_pressed(0, GamepadButton::South));
+        assert_eq!(gc.axis_value(0, GamepadAxis::LeftStickX), 0.5);
+        // Deadzone
+        assert_eq!(gc.axis_value(0, GamepadAxis::LeftStickY), 0.0);
+
+        gc.save_buttons();
+        assert!(gc.just_connected().is_empty());
+        assert!(!gc.button_just_pressed(0, GamepadButton::South));
+
+        gc.disconnect(0);
+        assert_eq!(gc.just_disconnected(), [0]);
+        assert!(gc.button_released(0, GamepadButton::South));
+        assert_eq!(gc.axis_value(0, GamepadAxis::LeftStickX), 0.0);
+    }
+
     #[test]
     fn modifer_pressed_test() {
         let mut kc = KeyboardContext::default();
@@ -322,4 +956,79 @@ mod tests {
         assert!(kc.modifier_released(KeyModifier::Shift));
         assert!(!kc.modifier_released(KeyModifier::Ctrl));
     }
+
+    #[test]
+    fn scroll_delta_reset_test() {
+        let mut mc = MouseContext::default();
+
+        mc.set_scroll_delta((0.0, 1.0));
+        assert_eq!(mc.scroll_delta(), (0.0, 1.0));
+
+        // Next frame without scrolling
+        mc.reset_scroll_delta();
+        assert_eq!(mc.scroll_delta(), (0.0, 0.0));
+    }
+
+    #[test]
+    fn events_test() {
+        let mut ic = InputContext::default();
+
+        // A tap within a single frame
+        ic.push_event(InputEvent::KeyPressed(KeyCode::A));
+        ic.push_event(InputEvent::KeyReleased(KeyCode::A));
+        assert_eq!(
+            ic.events(),
+            &[
+                InputEvent::KeyPressed(KeyCode::A),
+                InputEvent::KeyReleased(KeyCode::A)
+            ]
+        );
+
+        ic.clear_events();
+        assert!(ic.events().is_empty());
+    }
+
+    #[test]
+    fn scroll_total_test() {
+        let mut mc = MouseContext::default();
+
+        mc.set_scroll_delta((0.0, 1.0));
+        mc.reset_scroll_delta();
+        mc.set_scroll_delta((1.0, 2.0));
+        assert_eq!(mc.scroll_total(), (1.0, 3.0));
+
+        mc.reset_scroll_total();
+        assert_eq!(mc.scroll_total(), (0.0, 0.0));
+        assert_eq!(mc.scroll_delta(), (1.0, 2.0));
+    }
+
+    #[test]
+    fn mouse_delta_reset_test() {
+        let mut mc = MouseContext::default();
+
+        mc.set_mouse_delta((2.0, -3.0));
+        assert_eq!(mc.mouse_delta(), (2.0, -3.0));
+
+        // Next frame without motion
+        mc.reset_mouse_delta();
+        assert_eq!(mc.mouse_delta(), (0.0, 0.0));
+    }
+
+    #[test]
+    fn physical_to_pixel_test() {
+        // Render resolution at half the window size
+        assert_eq!(
+            physical_to_pixel((100.0, 50.0), (800, 600), (400, 300)),
+            (50, 25)
+        );
+        // Dragging outside the window stays on the edge pixels
+        assert_eq!(
+            physical_to_pixel((-20.0, 900.0), (800, 600), (400, 300)),
+            (0, 299)
+        );
+        assert_eq!(
+            physical_to_pixel((800.0, 600.0), (800, 600), (400, 300)),
+            (399, 299)
+        );
+    }
 }
@@ -1,6 +1,8 @@
 mod app;
 mod context;
+mod error;
 mod input;
+mod recording;
 mod render;
 mod time;
 mod window;
@@ -8,10 +10,36 @@ mod window;
 pub mod cmd;
 
 pub use app::run;
+pub use app::run_headless;
+pub use app::run_headless_with_config;
+pub use app::run_with_config;
 pub use app::Callbacks;
+pub use app::RunConfig;
+pub use cmd::camera::CameraPath;
+pub use cmd::camera::FlyState;
 pub use context::Context;
+pub use error::RaymarcherError;
+pub use input::GamepadAxis;
+pub use input::GamepadButton;
+pub use input::GamepadId;
+pub use input::InputBinding;
+pub use input::InputEvent;
 pub use input::KeyModifier;
+pub use render::DebugMode;
+pub use render::Material;
+pub use render::MaterialId;
 pub use render::Shape;
+pub use render::ShapeHandle;
+pub use render::Tonemap;
+pub use render::{MAX_MANDELBULB_ITERATIONS, MAX_MENGER_ITERATIONS, MAX_SHAPE_DEPTH};
 // pub use render::Shapes;
+pub use wgpu::Backends;
+pub use wgpu::Device;
+pub use wgpu::FilterMode;
+pub use wgpu::PowerPreference;
+pub use wgpu::PresentMode;
+pub use wgpu::Queue;
 pub use winit::event::MouseButton;
 pub use winit::event::VirtualKeyCode as KeyCode;
+pub use winit::window::CursorGrabMode;
+pub use winit::window::Icon;
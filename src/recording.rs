@@ -0,0 +1,65 @@
+use std::{fs::File, io::BufWriter, path::PathBuf};
+
+use crate::render::RenderContext;
+
+/// Frames written to disk after each render, see cmd::render::start_recording
+pub(crate) struct Recording {
+    pub(crate) dir: PathBuf,
+    // Index of the next frame to write
+    pub(crate) frame: u32,
+    pub(crate) frame_count: u32,
+    // Timestep before recording started, restored when it stops
+    pub(crate) prev_fixed_dt: Option<f32>,
+}
+
+impl Recording {
+    /// Returns the path of the given frame, numbered so the files sort in order
+    pub(crate) fn frame_path(&self, frame: u32) -> PathBuf {
+        self.dir.join(format!("frame_{frame:05}.png"))
+    }
+
+    /// Writes the most recently rendered frame as the next png
+    /// Returns true once all frames have been written
+    pub(crate) fn save_frame(
+        &mut self,
+        render: &RenderContext,
+    ) -> Result<bool, png::EncodingError> {
+        // Blocks until the frame is read back, this is what makes recording slower than real time
+        let pixels = render.read_texture(&render.texture, 4);
+        let (width, height) = (render.texture.width(), render.texture.height());
+
+        let file = File::create(self.frame_path(self.frame))?;
+        let mut encoder = png::Encoder::new(BufWriter::new(file), width, height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&pixels)?;
+        writer.finish()?;
+
+        self.frame += 1;
+        Ok(self.frame >= self.frame_count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::Recording;
+
+    #[test]
+    fn frame_path_test() {
+        let recording = Recording {
+            dir: PathBuf::from("out"),
+            frame: 0,
+            frame_count: 120,
+            prev_fixed_dt: None,
+        };
+        assert_eq!(
+            recording.frame_path(7),
+            PathBuf::from("out/frame_00007.png")
+        );
+        // Zero padding keeps lexical and numeric order the same
+        assert!(recording.frame_path(9) < recording.frame_path(10));
+    }
+}
@@ -1,45 +1,136 @@
 use encase::{ShaderType, StorageBuffer, UniformBuffer};
 use glam::Mat3;
-use glam::{uvec2, vec3, UVec2, Vec3};
+use glam::{uvec2, vec2, vec3, Vec3};
+use std::fs::File;
+use std::num::NonZeroU32;
+use std::path::Path;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
 use wgpu::{
-    util::DeviceExt, Adapter, BindGroup, Buffer, ComputePipeline, Device, Extent3d, PresentMode,
-    Queue, RenderPipeline, Surface, SurfaceConfiguration, TextureView,
+    util::DeviceExt, Adapter, BindGroup, BindGroupLayout, Buffer, ComputePipeline, Device,
+    Extent3d, PresentMode, Queue, RenderPipeline, Surface, SurfaceConfiguration, Texture,
+    TextureView,
 };
-use winit::window::Window;
+use winit::{dpi::PhysicalSize, window::Window};
 
-use crate::time::TimeContext;
+use crate::{app::RunConfig, error::RaymarcherError, time::TimeContext};
 
 pub const WIDTH: u32 = 1280;
 pub const HEIGHT: u32 = 720;
-pub const MAX_SHAPE_AMOUNT: u64 = 256;
+// The shape buffer grows beyond this when needed
+pub const INITIAL_SHAPE_CAPACITY: u64 = 256;
+// The instance buffer grows beyond this when needed
+const INITIAL_INSTANCE_CAPACITY: u64 = 256;
+// Size of each instance offset, vec3 array elements are aligned to 16 bytes
+const INSTANCE_STRIDE: u64 = 16;
+pub const MAX_MATERIAL_AMOUNT: u64 = 64;
+// Each nested operation takes a slot of the evaluation stack in the compute shader
+// Must match stack_size in the compute shader
+pub const MAX_SHAPE_DEPTH: usize = 10;
+// Iterations of Mandelbulb are clamped to this
+pub const MAX_MANDELBULB_ITERATIONS: u32 = 16;
+// Iterations of MengerSponge are clamped to this
+pub const MAX_MENGER_ITERATIONS: u32 = 8;
+
+// Must match the workgroup size of both passes in the bloom shader
+const BLOOM_WORKGROUP_SIZE: u32 = 8;
+
+// Mirror soft_shadow_sharpness and the furthest ambient occlusion sample in the compute shader
+// Culling keeps shapes which can still darken visible surfaces through them
+const SOFT_SHADOW_SHARPNESS: f32 = 8.0;
+const OCCLUSION_REACH: f32 = 0.5;
+
+// Default raymarching parameters
+const MAX_STEPS: u32 = 100;
+const MAX_DIST: f32 = 50.0;
+const SURFACE_DIST: f32 = 0.0001;
+
+// Light types
+pub(crate) const POINT_LIGHT: u32 = 0;
+pub(crate) const DIRECTIONAL_LIGHT: u32 = 1;
 
 pub struct RenderContext {
-    pub(crate) surface: wgpu::Surface,
+    // None when running headless
+    pub(crate) surface: Option<wgpu::Surface>,
     pub(crate) device: wgpu::Device,
     pub(crate) adapter: wgpu::Adapter,
     pub(crate) queue: wgpu::Queue,
 
     pub(crate) surface_config: wgpu::SurfaceConfiguration,
     pub(crate) window_size: winit::dpi::PhysicalSize<u32>,
-    pub(crate) window: Window,
+    pub(crate) window: Option<Window>,
 
     pub(crate) compute_pipeline: wgpu::ComputePipeline,
     pub(crate) compute_bind_group: wgpu::BindGroup,
     // These two are a part of the bind group
     pub(crate) input_buffer: wgpu::Buffer,
     pub(crate) global_uniform_buffer: wgpu::Buffer,
+    pub(crate) texture: wgpu::Texture,
     pub(crate) texture_view: wgpu::TextureView,
+    // Distance to the first hit per pixel
+    pub(crate) depth_texture: wgpu::Texture,
+    pub(crate) depth_texture_view: wgpu::TextureView,
+    // World space normal of the first hit per pixel
+    pub(crate) normal_texture: wgpu::Texture,
+    pub(crate) normal_texture_view: wgpu::TextureView,
+    // Index of the shape at the first hit, u32::MAX on a miss
+    pub(crate) id_texture: wgpu::Texture,
+    pub(crate) id_texture_view: wgpu::TextureView,
+    // Running average of the linear color per pixel
+    pub(crate) accumulation_buffer: wgpu::Buffer,
+    pub(crate) accumulating: bool,
+    // Frames averaged since the last reset
+    pub(crate) accumulated_frames: u32,
+    // Skip uploading top level shapes outside the view frustum
+    pub(crate) culling: bool,
 
     pub(crate) render_pipeline: wgpu::RenderPipeline,
     pub(crate) vertex_buffer: wgpu::Buffer,
     pub(crate) index_buffer: wgpu::Buffer,
     pub(crate) num_indices: u32,
     pub(crate) texture_bind_group: wgpu::BindGroup,
+    pub(crate) post_uniform_buffer: wgpu::Buffer,
+    pub(crate) bloom: BloomPass,
 
     pub(crate) globals: Globals,
+    pub(crate) post_globals: PostGlobals,
     pub(crate) resolution: (u32, u32),
     pub(crate) shapes: Vec<Shape>,
+    pub(crate) static_shapes: StaticShapes,
     // pub(crate) shapes: Shapes,
+    // Render resolution relative to the window size
+    pub(crate) render_scale: f32,
+    // Sampler filter used when stretching the render texture over the window
+    pub(crate) texture_filter: wgpu::FilterMode,
+    // Clear value of the render pass, visible where the render texture does not cover the window
+    pub(crate) clear_color: wgpu::Color,
+    // None if the adapter does not support timestamp queries
+    pub(crate) gpu_timer: Option<GpuTimer>,
+    // Amount of flattened shapes which fit in the input buffer
+    pub(crate) shape_capacity: u64,
+    // Largest shape_capacity the device can bind, shapes past it are not rendered
+    pub(crate) max_shape_capacity: u64,
+    // Avoids repeating the warning every frame while shapes are dropped
+    pub(crate) shapes_dropped: bool,
+    // Offsets of Shape::Instances, indexed by the instance nodes of the shape buffer
+    pub(crate) instance_buffer: wgpu::Buffer,
+    // Amount of offsets which fit in the instance buffer
+    pub(crate) instance_capacity: u64,
+    // Threads per compute workgroup, dispatches cover the resolution with these
+    pub(crate) workgroup_size: (u32, u32),
+    pub(crate) material_buffer: wgpu::Buffer,
+    // Index is the MaterialId, first entry is the default material
+    pub(crate) materials: Vec<Material>,
+    // Sampled by textured materials, a single white pixel until set_texture is called
+    pub(crate) surface_texture: wgpu::Texture,
+    pub(crate) surface_texture_view: wgpu::TextureView,
+    pub(crate) surface_sampler: wgpu::Sampler,
+    // Equirectangular image sampled by missed rays, used when globals.environment_enabled is set
+    pub(crate) environment_texture: wgpu::Texture,
+    pub(crate) environment_texture_view: wgpu::TextureView,
+    pub(crate) environment_sampler: wgpu::Sampler,
 }
 
 #[derive(Debug, Clone)]
@@ -56,6 +147,54 @@ pub enum Shape {
         pos: Vec3,
         normal: Vec3,
     },
+    /// Flat circular disk facing normal, a finite plane
+    Disk {
+        pos: Vec3,
+        normal: Vec3,
+        radius: f32,
+    },
+    /// Mandelbulb fractal, power 8 gives the classic bulb
+    /// Heavy: every distance evaluation runs up to iterations fractal iterations
+    /// Iterations are clamped to MAX_MANDELBULB_ITERATIONS, more add no visible detail
+    Mandelbulb {
+        pos: Vec3,
+        power: f32,
+        iterations: u32,
+    },
+    /// Menger sponge fractal filling a cube with half extent size
+    /// Heavy: every distance evaluation runs iterations folds, each adding a level of holes
+    /// Iterations are clamped to MAX_MENGER_ITERATIONS, finer holes are smaller than a pixel
+    MengerSponge {
+        pos: Vec3,
+        size: f32,
+        iterations: u32,
+    },
+    /// Square based pyramid with the base centered at pos and the apex height above it
+    /// base and height must be above 0
+    Pyramid {
+        pos: Vec3,
+        base: f32,
+        height: f32,
+    },
+    /// Chain link centered at pos, lying in the xy plane and elongated along y
+    /// length: Length of the two straight sides, 0 gives a torus
+    /// inner_radius: Radius of the round ends, measured to the middle of the tube
+    /// tube_radius: Thickness of the tube, clamped to inner_radius so the hole can not invert
+    Link {
+        pos: Vec3,
+        length: f32,
+        inner_radius: f32,
+        tube_radius: f32,
+    },
+    /// Shape with a user defined distance, see RunConfig::custom_sdf
+    /// id, v1 and f1 are passed to sdf_custom as is
+    /// Not evaluated on the cpu, so it is never picked or culled
+    Custom {
+        id: u32,
+        pos: Vec3,
+        v1: Vec3,
+        f1: f32,
+    },
     Union {
         shape1: Box<Shape>,
         shape2: Box<Shape>,
@@ -68,148 +207,1635 @@ pub enum Shape {
         shape1: Box<Shape>,
         shape2: Box<Shape>,
     },
+    /// Repeats shape infinitely with the given spacing
+    /// The cell around center is the original, usually center is the position of the shape
+    /// A spacing of 0 disables repetition along that axis
+    Repeat {
+        shape: Box<Shape>,
+        center: Vec3,
+        spacing: Vec3,
+    },
+    /// Repeats shape with the given spacing, count times in each direction per axis
+    /// The cell around center is the original, usually center is the position of the shape
+    /// A count of (2, 0, 2) gives a 5x1x5 grid
+    /// A spacing of 0 disables repetition along that axis
+    RepeatLimited {
+        shape: Box<Shape>,
+        center: Vec3,
+        spacing: Vec3,
+        count: Vec3,
+    },
+    /// Rotates shape around the origin
+    Rotate {
+        shape: Box<Shape>,
+        rotation: Mat3,
+    },
+    /// Rotates shape around center, e.g. the position of the shape to spin it in place
+    RotateAround {
+        shape: Box<Shape>,
+        center: Vec3,
+        rotation: Mat3,
+    },
+    /// Scales shape uniformly around the origin
+    /// Resulting distance is multiplied by factor to remain a valid distance field
+    /// factor must be above 0
+    Scale {
+        shape: Box<Shape>,
+        factor: f32,
+    },
+    /// Bends shape by rotating the xy plane by amount * x around center
+    /// The bend does not preserve distances so the result is scaled down to stay safe,
+    /// large amounts can still overstep and produce artifacts
+    Bend {
+        shape: Box<Shape>,
+        center: Vec3,
+        amount: f32,
+    },
+    /// Turns shape into a shell of the given thickness around its surface
+    /// Nesting onions gives concentric shells
+    Onion {
+        shape: Box<Shape>,
+        thickness: f32,
+    },
+    /// Moves shape around center along a circle in the xz plane, animated on the gpu
+    /// The shape is placed relative to the point on the circle, so one at the origin orbits center
+    /// speed: Radians per second of shader time, negative orbits the other way
+    /// The cpu does not know the shader time, so dist and bounds cover the whole orbit
+    Orbit {
+        shape: Box<Shape>,
+        center: Vec3,
+        radius: f32,
+        speed: f32,
+    },
+    /// Copies of shape placed at each offset from its position
+    /// All copies are evaluated in a single node with the offsets kept in a separate buffer,
+    /// so many copies of the same shape barely grow the shape buffer
+    /// shape must be a single primitive, optionally with a material, flattening panics otherwise
+    Instances {
+        shape: Box<Shape>,
+        offsets: Vec<Vec3>,
+    },
+    /// Shows or hides shape, hidden shapes are infinitely far away
+    /// Toggling keeps the flattened layout, so material ids and picked indices stay stable
+    Visible {
+        shape: Box<Shape>,
+        visible: bool,
+    },
+    /// Applies material to all shapes within
+    /// Inner materials take precedence over outer ones
+    Material {
+        shape: Box<Shape>,
+        material: MaterialId,
+    },
+}
+
+pub(crate) use gpu_layout::{Globals, PostGlobals};
+pub use gpu_layout::{Material, ShapeGPU};
+
+// Types uploaded to the gpu, kept apart since encase's ShaderType derive
+// emits an unused `check` fn per field on recent compilers
+#[allow(dead_code)]
+mod gpu_layout {
+    use encase::ShaderType;
+    use glam::{Mat3, UVec2, Vec3};
+
+    /// Surface properties of a shape
+    /// Stored in a material table on the gpu and referenced by MaterialId
+    #[derive(Debug, Clone, ShaderType)]
+    pub struct Material {
+        pub color: Vec3,
+        /// Amount of reflected color mixed into the surface color, 0 disables reflections
+        pub reflectivity: f32,
+        /// Light emitted by the surface, added regardless of lighting
+        /// Emissive shapes still occlude rays and cast shadows but are never darkened themselves
+        pub emissive: Vec3,
+        /// Opacity, below 1 the first surface behind the shape is blended in
+        /// Only a single transparent layer is seen through and transparent shapes cast full shadows
+        pub alpha: f32,
+        /// Index of refraction of transparent materials, 1 passes rays straight through
+        /// Glass is around 1.5 and water around 1.33
+        pub ior: f32,
+        /// Repetitions per unit of the texture set with set_texture, 0 disables texturing
+        /// The texture is projected along each axis and blended by the surface normal (triplanar)
+        /// and multiplied with color
+        pub texture_scale: f32,
+        /// Strength of light scattered through thin parts of the shape, e.g. wax or skin
+        /// Approximated from the thickness below the surface, 0 disables it
+        pub translucency: f32,
+        /// Strength of the highlight of the light, 0 disables it
+        pub specular: f32,
+        /// Spread of the highlight in [0, 1], lower gives smaller and sharper highlights
+        pub roughness: f32,
+    }
+
+    #[derive(Default, Debug, Clone, ShaderType)]
+    pub struct ShapeGPU {
+        pub pos: Vec3,
+        pub id: u32,
+        pub v1: Vec3,
+        pub f1: f32,
+        pub v2: Vec3,
+        pub f2: f32,
+        pub m1: Mat3,
+        pub material: u32,
+        // Index of the top level shape this node belongs to, written to the id texture
+        pub index: u32,
+    }
+
+    /// Settings of the render pass presenting the texture
+    /// Mirrored by PostGlobals in the render and bloom shaders
+    #[derive(Debug, Clone, Default, ShaderType)]
+    pub(crate) struct PostGlobals {
+        pub(crate) focus_distance: f32,
+        // Depth of field is disabled at 0
+        pub(crate) aperture: f32,
+        pub(crate) bloom_threshold: f32,
+        // Bloom is disabled at 0
+        pub(crate) bloom_intensity: f32,
+        pub(crate) outline_color: Vec3,
+        // Outlines are disabled at 0
+        pub(crate) outline_thickness: f32,
+    }
+
+    // ShaderType auto pads!
+    // Try to minimize size
+    #[derive(Debug, Clone, ShaderType)]
+    pub(crate) struct Globals {
+        pub(crate) screen_dim: UVec2,
+        pub(crate) camera_pos: Vec3,
+        pub(crate) camera_rot: Mat3,
+        pub(crate) light_pos: Vec3,
+        pub(crate) focal_length: f32,
+        pub(crate) time: f32,
+        pub(crate) shape_amount: u32,
+        pub(crate) max_steps: u32,
+        pub(crate) max_dist: f32,
+        pub(crate) surface_dist: f32,
+        pub(crate) max_bounces: u32,
+        pub(crate) gamma_correction: u32,
+        pub(crate) light_dir: Vec3,
+        pub(crate) light_type: u32,
+        pub(crate) light_color: Vec3,
+        pub(crate) debug_mode: u32,
+        pub(crate) checker_enabled: u32,
+        pub(crate) checker_scale: f32,
+        pub(crate) checker_color_a: Vec3,
+        pub(crate) checker_color_b: Vec3,
+        pub(crate) fog_color: Vec3,
+        pub(crate) fog_density: f32,
+        pub(crate) aa_samples: u32,
+        pub(crate) grid_enabled: u32,
+        pub(crate) grid_spacing: f32,
+        pub(crate) grid_color: Vec3,
+        pub(crate) tonemap: u32,
+        pub(crate) exposure: f32,
+        pub(crate) vignette_strength: f32,
+        pub(crate) vignette_radius: f32,
+        // Frames averaged including the current one, 0 when not accumulating
+        pub(crate) frame_index: u32,
+        pub(crate) normal_epsilon: f32,
+        pub(crate) rim_color: Vec3,
+        pub(crate) rim_power: f32,
+        pub(crate) max_internal_reflections: u32,
+        pub(crate) shadow_bias: f32,
+        pub(crate) environment_enabled: u32,
+        pub(crate) seed: u32,
+        pub(crate) ambient_color: Vec3,
+    }
+}
+
+impl Material {
+    /// Material which only emits color, unaffected by lights and shadows
+    pub fn emissive(color: Vec3) -> Self {
+        Self {
+            color: Vec3::ZERO,
+            reflectivity: 0.0,
+            emissive: color,
+            alpha: 1.0,
+            ior: 1.0,
+            texture_scale: 0.0,
+            translucency: 0.0,
+            specular: 0.3,
+            roughness: 0.5,
+        }
+    }
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Self {
+            color: vec3(0.0, 1.0, 1.0),
+            reflectivity: 0.0,
+            emissive: Vec3::ZERO,
+            alpha: 1.0,
+            ior: 1.0,
+            texture_scale: 0.0,
+            translucency: 0.0,
+            specular: 0.3,
+            roughness: 0.5,
+        }
+    }
+}
+
+/// Handle to a material added with add_material
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MaterialId(pub(crate) u32);
+
+impl MaterialId {
+    /// Material used by shapes without a material, always available
+    pub const DEFAULT: MaterialId = MaterialId(0);
+}
+
+impl Default for MaterialId {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// Alternative outputs of the compute shader used for debugging
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DebugMode {
+    /// Regular shading
+    #[default]
+    None = 0,
+    /// Surface normals as rgb, 0.5 * normal + 0.5
+    Normals = 1,
+    /// Amount of march steps relative to max steps, blue for few and red for many
+    StepCount = 2,
+    /// Regular shading with the bounding box of each leaf shape drawn as wireframe
+    /// Unbounded shapes such as planes and repetitions are not drawn
+    Bounds = 3,
+}
+
+/// Mapping of unbounded shaded colors into the displayable [0, 1] range
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Tonemap {
+    /// Colors above 1 are clipped
+    #[default]
+    None = 0,
+    /// c / (1 + c), compresses highlights but desaturates and darkens midtones
+    Reinhard = 1,
+    /// Filmic curve fit of the ACES reference transform, keeps contrast
+    Aces = 2,
+}
+
+impl Shape {
+    /// Returns the signed distance from pos to the shape
+    /// Mirrors the sdf functions in the compute shader
+    pub fn dist(&self, pos: Vec3) -> f32 {
+        match self {
+            Shape::Sphere {
+                pos: center,
+                radius,
+            } => (pos - *center).length() - radius,
+            Shape::BoxExact { pos: center, b } => {
+                let q = (pos - *center).abs() - *b;
+                q.max(Vec3::ZERO).length() + q.max_element().min(0.0)
+            }
+            Shape::Plane { pos: point, normal } => (pos - *point).dot(*normal),
+            Shape::Disk {
+                pos: center,
+                normal,
+                radius,
+            } => disk_dist(pos - *center, normal.normalize(), *radius),
+            Shape::Mandelbulb {
+                pos: center,
+                power,
+                iterations,
+            } => {
+                let iterations = (*iterations).min(MAX_MANDELBULB_ITERATIONS);
+                mandelbulb_dist(pos - *center, *power, iterations)
+            }
+            Shape::MengerSponge {
+                pos: center,
+                size,
+                iterations,
+            } => {
+                let iterations = (*iterations).min(MAX_MENGER_ITERATIONS);
+                menger_dist((pos - *center) / *size, iterations) * size
+            }
+            Shape::Pyramid {
+                pos: center,
+                base,
+                height,
+            } => {
+                assert_pyramid_size(*base, *height);
+                pyramid_dist((pos - *center) / *base, *height / *base) * base
+            }
+            Shape::Link {
+                pos: center,
+                length,
+                inner_radius,
+                tube_radius,
+            } => {
+                let (half_length, r1, r2) = link_params(*length, *inner_radius, *tube_radius);
+                let p = pos - *center;
+                let q = vec2(p.x, (p.y.abs() - half_length).max(0.0));
+                vec2(q.length() - r1, p.z).length() - r2
+            }
+            Shape::Custom { .. } => f32::INFINITY,
+            Shape::Union { shape1, shape2 } => shape1.dist(pos).min(shape2.dist(pos)),
+            Shape::Intersection { shape1, shape2 } => shape1.dist(pos).max(shape2.dist(pos)),
+            Shape::Subtraction { shape1, shape2 } => shape1.dist(pos).max(-shape2.dist(pos)),
+            Shape::Repeat {
+                shape,
+                center,
+                spacing,
+            } => shape.dist(repeat(pos - *center, *spacing) + *center),
+            Shape::RepeatLimited {
+                shape,
+                center,
+                spacing,
+                count,
+            } => shape.dist(repeat_limited(pos - *center, *spacing, *count) + *center),
+            Shape::Rotate { shape, rotation } => shape.dist(rotation.inverse() * pos),
+            Shape::RotateAround {
+                shape,
+                center,
+                rotation,
+            } => shape.dist(rotation.inverse() * (pos - *center) + *center),
+            Shape::Scale { shape, factor } => {
+                assert_scale_factor(*factor);
+                shape.dist(pos / *factor) * factor
+            }
+            Shape::Bend {
+                shape,
+                center,
+                amount,
+            } => shape.dist(bend(pos - *center, *amount) + *center) * bend_safety(*amount),
+            Shape::Onion { shape, thickness } => shape.dist(pos).abs() - thickness,
+            // Closest the shape gets at any point of the orbit
+            Shape::Orbit {
+                shape,
+                center,
+                radius,
+                ..
+            } => shape.dist(pos - *center) - radius.abs(),
+            Shape::Instances { shape, offsets } => offsets
+                .iter()
+                .map(|offset| shape.dist(pos - *offset))
+                .fold(f32::INFINITY, f32::min),
+            Shape::Visible { shape, visible } => {
+                if *visible {
+                    shape.dist(pos)
+                } else {
+                    f32::INFINITY
+                }
+            }
+            Shape::Material { shape, .. } => shape.dist(pos),
+        }
+    }
+}
+
+impl Shape {
+    /// Moves the shape by offset in world space
+    pub fn translate(&mut self, offset: Vec3) {
+        match self {
+            Shape::Sphere { pos, .. }
+            | Shape::BoxExact { pos, .. }
+            | Shape::Plane { pos, .. }
+            | Shape::Disk { pos, .. }
+            | Shape::Mandelbulb { pos, .. }
+            | Shape::MengerSponge { pos, .. }
+            | Shape::Pyramid { pos, .. }
+            | Shape::Link { pos, .. }
+            | Shape::Custom { pos, .. } => *pos += offset,
+            Shape::Union { shape1, shape2 }
+            | Shape::Intersection { shape1, shape2 }
+            | Shape::Subtraction { shape1, shape2 } => {
+                shape1.translate(offset);
+                shape2.translate(offset);
+            }
+            Shape::Onion { shape, .. }
+            | Shape::Instances { shape, .. }
+            | Shape::Visible { shape, .. }
+            | Shape::Material { shape, .. } => shape.translate(offset),
+            // Operands live in the rotated/scaled space
+            Shape::Rotate { shape, rotation } => shape.translate(rotation.inverse() * offset),
+            Shape::Scale { shape, factor } => shape.translate(offset / *factor),
+            Shape::Orbit { center, .. } => *center += offset,
+            // Moving the center along keeps the operation rigid, the operand alone would move
+            // within the repeated cell or the bent space
+            Shape::RotateAround { shape, center, .. }
+            | Shape::Repeat { shape, center, .. }
+            | Shape::RepeatLimited { shape, center, .. }
+            | Shape::Bend { shape, center, .. } => {
+                *center += offset;
+                shape.translate(offset);
+            }
+        }
+    }
+
+    /// Returns a conservative world space axis aligned bounding box as (min, max)
+    /// Unbounded axes, e.g. of planes or repetitions, are infinite
+    /// Hidden shapes are empty with min > max
+    pub fn bounds(&self) -> (Vec3, Vec3) {
+        match self {
+            Shape::Sphere { pos, radius } => (*pos - *radius, *pos + *radius),
+            Shape::BoxExact { pos, b } => (*pos - *b, *pos + *b),
+            Shape::Plane { .. } => (Vec3::NEG_INFINITY, Vec3::INFINITY),
+            Shape::Disk {
+                pos,
+                normal,
+                radius,
+            } => {
+                let n = normal.normalize();
+                // Extent of the rim along each axis
+                let e = (Vec3::ONE - n * n).max(Vec3::ZERO);
+                let extent = *radius * vec3(e.x.sqrt(), e.y.sqrt(), e.z.sqrt());
+                (*pos - extent, *pos + extent)
+            }
+            // Points further than the escape radius diverge immediately
+            Shape::Mandelbulb { pos, .. } => (*pos - 2.0, *pos + 2.0),
+            Shape::MengerSponge { pos, size, .. } => (*pos - *size, *pos + *size),
+            Shape::Pyramid { pos, base, height } => (
+                *pos - vec3(0.5 * base, 0.0, 0.5 * base),
+                *pos + vec3(0.5 * base, *height, 0.5 * base),
+            ),
+            Shape::Link {
+                pos,
+                length,
+                inner_radius,
+                tube_radius,
+            } => {
+                let (half_length, r1, r2) = link_params(*length, *inner_radius, *tube_radius);
+                let extent = vec3(r1 + r2, half_length + r1 + r2, r2);
+                (*pos - extent, *pos + extent)
+            }
+            Shape::Custom { .. } => (Vec3::NEG_INFINITY, Vec3::INFINITY),
+            Shape::Union { shape1, shape2 } => {
+                let (min1, max1) = shape1.bounds();
+                let (min2, max2) = shape2.bounds();
+                (min1.min(min2), max1.max(max2))
+            }
+            Shape::Intersection { shape1, shape2 } => {
+                let (min1, max1) = shape1.bounds();
+                let (min2, max2) = shape2.bounds();
+                (min1.max(min2), max1.min(max2))
+            }
+            Shape::Subtraction { shape1, .. } => shape1.bounds(),
+            Shape::Repeat { shape, .. }
+            | Shape::RepeatLimited { shape, .. }
+            | Shape::Rotate { shape, .. }
+            | Shape::RotateAround { shape, .. }
+            | Shape::Scale { shape, .. }
+            | Shape::Bend { shape, .. }
+            | Shape::Onion { shape, .. }
+            | Shape::Orbit { shape, .. }
+            | Shape::Instances { shape, .. }
+            | Shape::Visible { shape, .. }
+            | Shape::Material { shape, .. } => self.wrap_bounds(shape.bounds()),
+        }
+    }
+
+    /// Applies a unary operation to the bounds of its operand
+    fn wrap_bounds(&self, (min, max): (Vec3, Vec3)) -> (Vec3, Vec3) {
+        if min.cmpgt(max).any() {
+            return (min, max);
+        }
+        match self {
+            Shape::Repeat { spacing, .. } => {
+                let repeated = spacing.cmpne(Vec3::ZERO);
+                (
+                    Vec3::select(repeated, Vec3::NEG_INFINITY, min),
+                    Vec3::select(repeated, Vec3::INFINITY, max),
+                )
+            }
+            Shape::RepeatLimited { spacing, count, .. } => {
+                let extent = (*spacing * *count).abs();
+                (min - extent, max + extent)
+            }
+            Shape::Rotate { rotation, .. } => rotate_bounds(*rotation, Vec3::ZERO, (min, max)),
+            Shape::RotateAround {
+                center, rotation, ..
+            } => rotate_bounds(*rotation, *center, (min, max)),
+            Shape::Scale { factor, .. } => (min * *factor, max * *factor),
+            // Bending rotates around the z axis through center, preserving the distance to it
+            Shape::Bend { center, .. } => {
+                let (min, max) = (min - *center, max - *center);
+                let x = min.x.abs().max(max.x.abs());
+                let y = min.y.abs().max(max.y.abs());
+                let r = vec2(x, y).length();
+                (
+                    vec3(center.x - r, center.y - r, min.z + center.z),
+                    vec3(center.x + r, center.y + r, max.z + center.z),
+                )
+            }
+            Shape::Onion { thickness, .. } => (min - thickness.abs(), max + thickness.abs()),
+            Shape::Orbit { center, radius, .. } => {
+                let extent = vec3(radius.abs(), 0.0, radius.abs());
+                (min + *center - extent, max + *center + extent)
+            }
+            Shape::Instances { offsets, .. } => offsets
+                .iter()
+                .fold((Vec3::INFINITY, Vec3::NEG_INFINITY), |(lo, hi), offset| {
+                    (lo.min(min + *offset), hi.max(max + *offset))
+                }),
+            Shape::Visible { visible: false, .. } => (Vec3::INFINITY, Vec3::NEG_INFINITY),
+            _ => (min, max),
+        }
+    }
+
+    /// Returns the amount of nodes the shape is flattened into on the gpu
+    /// Material wrappers are not counted since they only tag the shapes below them
+    pub fn node_count(&self) -> usize {
+        match self {
+            Shape::Union { shape1, shape2 }
+            | Shape::Intersection { shape1, shape2 }
+            | Shape::Subtraction { shape1, shape2 } => {
+                1 + shape1.node_count() + shape2.node_count()
+            }
+            Shape::Repeat { shape, .. }
+            | Shape::RepeatLimited { shape, .. }
+            | Shape::Rotate { shape, .. }
+            | Shape::RotateAround { shape, .. }
+            | Shape::Scale { shape, .. }
+            | Shape::Bend { shape, .. }
+            | Shape::Onion { shape, .. }
+            | Shape::Orbit { shape, .. }
+            | Shape::Instances { shape, .. }
+            | Shape::Visible { shape, .. } => 1 + shape.node_count(),
+            Shape::Material { shape, .. } => shape.node_count(),
+            _ => 1,
+        }
+    }
+
+    /// Returns the amount of nested nodes along the deepest path, 1 for a single leaf
+    /// Shapes deeper than MAX_SHAPE_DEPTH overflow the evaluation stack of the compute shader
+    pub fn max_depth(&self) -> usize {
+        match self {
+            Shape::Union { shape1, shape2 }
+            | Shape::Intersection { shape1, shape2 }
+            | Shape::Subtraction { shape1, shape2 } => {
+                1 + shape1.max_depth().max(shape2.max_depth())
+            }
+            Shape::Repeat { shape, .. }
+            | Shape::RepeatLimited { shape, .. }
+            | Shape::Rotate { shape, .. }
+            | Shape::RotateAround { shape, .. }
+            | Shape::Scale { shape, .. }
+            | Shape::Bend { shape, .. }
+            | Shape::Onion { shape, .. }
+            | Shape::Orbit { shape, .. }
+            | Shape::Visible { shape, .. } => 1 + shape.max_depth(),
+            // Instances are combined in place without an entry on the evaluation stack
+            Shape::Material { shape, .. } | Shape::Instances { shape, .. } => shape.max_depth(),
+            _ => 1,
+        }
+    }
+
+    /// Returns an error if the shape is nested deeper than MAX_SHAPE_DEPTH
+    /// Deeper shapes would overflow the evaluation stack of the compute shader and render garbage
+    pub fn check_depth(&self) -> Result<(), RaymarcherError> {
+        let depth = self.max_depth();
+        if depth > MAX_SHAPE_DEPTH {
+            return Err(RaymarcherError::ShapeTooDeep(depth));
+        }
+        Ok(())
+    }
+
+    /// Pushes the world space bounds of every leaf shape in flattening order
+    /// ancestors: Unary operations enclosing this shape, outermost first
+    fn leaf_bounds<'a>(&'a self, ancestors: &mut Vec<&'a Shape>, out: &mut Vec<(Vec3, Vec3)>) {
+        match self {
+            Shape::Union { shape1, shape2 }
+            | Shape::Intersection { shape1, shape2 }
+            | Shape::Subtraction { shape1, shape2 } => {
+                shape1.leaf_bounds(ancestors, out);
+                shape2.leaf_bounds(ancestors, out);
+            }
+            Shape::Repeat { shape, .. }
+            | Shape::RepeatLimited { shape, .. }
+            | Shape::Rotate { shape, .. }
+            | Shape::RotateAround { shape, .. }
+            | Shape::Scale { shape, .. }
+            | Shape::Bend { shape, .. }
+            | Shape::Onion { shape, .. }
+            | Shape::Orbit { shape, .. }
+            | Shape::Instances { shape, .. }
+            | Shape::Visible { shape, .. }
+            | Shape::Material { shape, .. } => {
+                ancestors.push(self);
+                shape.leaf_bounds(ancestors, out);
+                ancestors.pop();
+            }
+            _ => {
+                let bounds = ancestors
+                    .iter()
+                    .rev()
+                    .fold(self.bounds(), |bounds, op| op.wrap_bounds(bounds));
+                out.push(bounds);
+            }
+        }
+    }
+}
+
+/// Returns true if no ray of the frame can reach the bounds (min, max)
+/// Shapes outside the view are kept while they could shadow a visible surface, i.e. the light is
+/// beyond them, or when scattering materials can reflect or refract rays towards them
+/// Unbounded and inverted bounds are never culled
+fn culled(g: &Globals, scattering: bool, (min, max): (Vec3, Vec3)) -> bool {
+    if min.cmpgt(max).any() || !min.is_finite() || !max.is_finite() {
+        return false;
+    }
+    let point_light = g.light_type == POINT_LIGHT;
+    let light_offset = g.light_pos - g.camera_pos;
+
+    // Camera rays and each scattered bounce travel at most max_dist
+    let ray_reach = if scattering {
+        g.max_dist * (1 + g.max_bounces) as f32
+    } else {
+        g.max_dist
+    };
+    // Soft shadows are darkened by shapes within t / sharpness of the shadow ray at distance t
+    // Occlusion samples a short distance away from surfaces
+    let shadow_length = if point_light {
+        ray_reach + light_offset.length()
+    } else {
+        g.max_dist
+    };
+    let margin = shadow_length / SOFT_SHADOW_SHARPNESS + OCCLUSION_REACH;
+    let reach = if point_light {
+        ray_reach.max(light_offset.length())
+    } else {
+        ray_reach + g.max_dist
+    };
+
+    // Closest point of the bounds is too far to be reached
+    let closest = (min - g.camera_pos).max(g.camera_pos - max).max(Vec3::ZERO);
+    if closest.length() > reach + margin {
+        return true;
+    }
+    if scattering {
+        return false;
+    }
+
+    // Corners in camera space, the view spans |x| <= z / focal_length and likewise for y
+    let inverse_rot = g.camera_rot.inverse();
+    let corners: Vec<Vec3> = (0..8)
+        .map(|i| {
+            let corner = vec3(
+                if i & 1 == 0 { min.x } else { max.x },
+                if i & 2 == 0 { min.y } else { max.y },
+                if i & 4 == 0 { min.z } else { max.z },
+            );
+            inverse_rot * (corner - g.camera_pos)
+        })
+        .collect();
+    // Shadow rays run from visible surfaces towards the light, they stay within the view side of a
+    // plane only if the light is on that side too
+    let light = if point_light {
+        inverse_rot * light_offset
+    } else {
+        inverse_rot * -g.light_dir
+    };
+    let f = g.focal_length;
+    let planes = [
+        vec3(0.0, 0.0, -1.0),
+        vec3(f, 0.0, -1.0),
+        vec3(-f, 0.0, -1.0),
+        vec3(0.0, f, -1.0),
+        vec3(0.0, -f, -1.0),
+    ];
+    // Culled if every corner is further than margin on the outer side of the same plane
+    planes
+        .iter()
+        .map(|plane| plane.normalize())
+        .filter(|plane| plane.dot(light) <= 0.0)
+        .any(|plane| corners.iter().all(|corner| plane.dot(*corner) > margin))
+}
+
+/// Bounds of the corners of (min, max) rotated around center
+fn rotate_bounds(rotation: Mat3, center: Vec3, (min, max): (Vec3, Vec3)) -> (Vec3, Vec3) {
+    if !min.is_finite() || !max.is_finite() {
+        return (Vec3::NEG_INFINITY, Vec3::INFINITY);
+    }
+    let corners = (0..8).map(|i| {
+        let select = |bit: u32, lo: f32, hi: f32| if i & bit == 0 { lo } else { hi };
+        let corner = vec3(
+            select(1, min.x, max.x),
+            select(2, min.y, max.y),
+            select(4, min.z, max.z),
+        );
+        rotation * (corner - center) + center
+    });
+    corners.fold((Vec3::INFINITY, Vec3::NEG_INFINITY), |(lo, hi), corner| {
+        (lo.min(corner), hi.max(corner))
+    })
 }
 
-pub fn shapes_to_gpu(shapes: &Vec<Shape>) -> ShapesGPU {
-    let mut gpu_shapes = ShapesGPU(Vec::new());
-    for shape in shapes.iter() {
-        gpu_shapes.add(shape);
+/// Distance from p to a disk centered at the origin
+/// Outside the rim the closest point is on the edge rather than the face
+fn disk_dist(p: Vec3, normal: Vec3, radius: f32) -> f32 {
+    let height = p.dot(normal);
+    let radial = (p - normal * height).length();
+    vec2((radial - radius).max(0.0), height).length()
+}
+
+/// Maps pos into the repeated cell centered around the origin
+fn repeat(pos: Vec3, spacing: Vec3) -> Vec3 {
+    let repeat_axis = |p: f32, s: f32| {
+        if s == 0.0 {
+            p
+        } else {
+            (p + 0.5 * s).rem_euclid(s) - 0.5 * s
+        }
+    };
+    vec3(
+        repeat_axis(pos.x, spacing.x),
+        repeat_axis(pos.y, spacing.y),
+        repeat_axis(pos.z, spacing.z),
+    )
+}
+
+/// Maps pos into the repeated cell centered around the origin
+/// Cell index is clamped to [-count, count]
+fn repeat_limited(pos: Vec3, spacing: Vec3, count: Vec3) -> Vec3 {
+    let repeat_axis = |p: f32, s: f32, c: f32| {
+        if s == 0.0 {
+            p
+        } else {
+            p - s * (p / s).round().clamp(-c, c)
+        }
+    };
+    vec3(
+        repeat_axis(pos.x, spacing.x, count.x),
+        repeat_axis(pos.y, spacing.y, count.y),
+        repeat_axis(pos.z, spacing.z, count.z),
+    )
+}
+
+/// Distance estimate of a mandelbulb centered at the origin
+fn mandelbulb_dist(pos: Vec3, power: f32, iterations: u32) -> f32 {
+    let mut z = pos;
+    let mut dr = 1.0;
+    let mut r = 0.0;
+    for _ in 0..iterations {
+        r = z.length();
+        if r > 2.0 {
+            break;
+        }
+        let theta = (z.z / r.max(1e-6)).clamp(-1.0, 1.0).acos() * power;
+        let phi = z.y.atan2(z.x) * power;
+        dr = r.powf(power - 1.0) * power * dr + 1.0;
+        let zr = r.powf(power);
+        z =
+            zr * vec3(
+                theta.sin() * phi.cos(),
+                theta.sin() * phi.sin(),
+                theta.cos(),
+            ) + pos;
+    }
+    // Avoid log(0) at the center
+    let r = r.max(1e-6);
+    0.5 * r.ln() * r / dr
+}
+
+/// Returns the (half length, inner radius, tube radius) of a link with degenerate values clamped
+fn link_params(length: f32, inner_radius: f32, tube_radius: f32) -> (f32, f32, f32) {
+    let inner_radius = inner_radius.max(0.0);
+    (
+        0.5 * length.max(0.0),
+        inner_radius,
+        tube_radius.clamp(0.0, inner_radius),
+    )
+}
+
+/// Points are divided by the factor, a zero or negative factor gives NaN or inverts the shape
+fn assert_scale_factor(factor: f32) {
+    assert!(factor > 0.0, "scale factor must be above 0");
+}
+
+/// Pyramids are evaluated scaled by base, so empty or negative sizes give NaN
+fn assert_pyramid_size(base: f32, height: f32) {
+    assert!(
+        base > 0.0 && height > 0.0,
+        "pyramid base and height must be above 0"
+    );
+}
+
+/// Distance of a pyramid with a unit wide base centered at the origin
+fn pyramid_dist(pos: Vec3, height: f32) -> f32 {
+    // Below the base, or without height, the closest point is on the base square
+    // The face formula below degenerates into a plane without height
+    if pos.y <= 0.0 || height <= 1e-6 {
+        let q = pos.abs() - vec3(0.5, 0.0, 0.5);
+        return q.max(Vec3::ZERO).length();
+    }
+    let m2 = height * height + 0.25;
+    // Fold into a single face
+    let (mut x, mut z) = (pos.x.abs(), pos.z.abs());
+    if z > x {
+        (x, z) = (z, x);
+    }
+    x -= 0.5;
+    z -= 0.5;
+    let y = pos.y;
+    let q = vec3(z, height * y - 0.5 * x, height * x + 0.5 * y);
+
+    let s = (-q.x).max(0.0);
+    let t = ((q.y - 0.5 * z) / (m2 + 0.25)).clamp(0.0, 1.0);
+    let a = m2 * (q.x + s) * (q.x + s) + q.y * q.y;
+    let b = m2 * (q.x + 0.5 * t) * (q.x + 0.5 * t) + (q.y - m2 * t) * (q.y - m2 * t);
+    let d2 = if q.y.min(-q.x * m2 - q.y * 0.5) > 0.0 {
+        0.0
+    } else {
+        a.min(b)
+    };
+    let d = ((d2 + q.z * q.z) / m2).sqrt() * q.z.max(-y).signum();
+    // Inside, the base may be closer than the faces
+    d.max(-y)
+}
+
+/// Distance of a unit menger sponge centered at the origin
+/// Bounded by its cube so distances outside are exact and rays pass by quickly
+fn menger_dist(pos: Vec3, iterations: u32) -> f32 {
+    let q = pos.abs() - Vec3::ONE;
+    let mut d = q.max(Vec3::ZERO).length() + q.max_element().min(0.0);
+    let mut s = 1.0;
+    for _ in 0..iterations {
+        let a = (pos * s).rem_euclid(Vec3::splat(2.0)) - Vec3::ONE;
+        s *= 3.0;
+        let r = (Vec3::ONE - 3.0 * a.abs()).abs();
+        let da = r.x.max(r.y);
+        let db = r.y.max(r.z);
+        let dc = r.z.max(r.x);
+        // Cross shaped hole carved at this level
+        let c = (da.min(db).min(dc) - 1.0) / s;
+        d = d.max(c);
+    }
+    d
+}
+
+/// Rotates the xy plane of pos by amount * pos.x
+fn bend(pos: Vec3, amount: f32) -> Vec3 {
+    let (s, c) = (amount * pos.x).sin_cos();
+    vec3(c * pos.x - s * pos.y, s * pos.x + c * pos.y, pos.z)
+}
+
+/// Factor keeping the distance of a bent shape from overstepping
+fn bend_safety(amount: f32) -> f32 {
+    1.0 / (1.0 + amount.abs())
+}
+
+/// Flattens shapes tagged with the index reported for them in the id texture
+pub fn indexed_shapes_to_gpu<'a>(
+    shapes: impl IntoIterator<Item = (usize, &'a Shape)>,
+) -> ShapesGPU {
+    let mut gpu_shapes = ShapesGPU(Vec::new(), Vec::new());
+    for (index, shape) in shapes {
+        gpu_shapes.add(shape, index as u32);
     }
     gpu_shapes
 }
 
-#[derive(Default, Debug, Clone, ShaderType)]
-pub struct ShapeGPU {
-    pub pos: Vec3,
-    pub id: u32,
-    pub v1: Vec3,
-    pub f1: f32,
+impl ShapeGPU {
+    /// Mirrors is_leaf in the compute shader
+    fn is_leaf(&self) -> bool {
+        matches!(self.id, 6..=8 | 15 | 16 | 18..=21)
+    }
 }
 
+/// Flattened shapes and the offsets of their instance nodes
 #[derive(Debug, Clone)]
-pub struct ShapesGPU(Vec<ShapeGPU>);
+pub struct ShapesGPU(Vec<ShapeGPU>, Vec<Vec3>);
 
 impl ShapesGPU {
-    pub fn add(&mut self, shape: &Shape) {
+    pub fn add(&mut self, shape: &Shape, index: u32) {
+        let start = self.0.len();
+        self.add_with_material(shape, MaterialId::DEFAULT);
+        for node in &mut self.0[start..] {
+            node.index = index;
+        }
+
+        // m1 of leaves holds their bounds for DebugMode::Bounds, zero if unbounded or hidden
+        let mut bounds = Vec::new();
+        shape.leaf_bounds(&mut Vec::new(), &mut bounds);
+        let leaves = self.0[start..].iter_mut().filter(|s| s.is_leaf());
+        for (leaf, (min, max)) in leaves.zip(bounds) {
+            let drawable = min.is_finite() && max.is_finite() && min.cmple(max).all();
+            leaf.m1 = if drawable {
+                Mat3::from_cols(min, max, Vec3::ZERO)
+            } else {
+                Mat3::ZERO
+            };
+        }
+    }
+
+    /// Adds shape with the material applied to all its leaf shapes
+    fn add_with_material(&mut self, shape: &Shape, material: MaterialId) {
         match shape {
             Shape::Union { shape1, shape2 } => {
                 self.0.push(ShapeGPU {
                     id: 0,
                     ..Default::default()
                 });
-                self.add(shape1);
-                self.add(shape2);
+                self.add_with_material(shape1, material);
+                self.add_with_material(shape2, material);
             }
             Shape::Intersection { shape1, shape2 } => {
                 self.0.push(ShapeGPU {
                     id: 1,
                     ..Default::default()
                 });
-                self.add(shape1);
-                self.add(shape2);
+                self.add_with_material(shape1, material);
+                self.add_with_material(shape2, material);
             }
             Shape::Subtraction { shape1, shape2 } => {
                 self.0.push(ShapeGPU {
                     id: 2,
                     ..Default::default()
                 });
-                self.add(shape1);
-                self.add(shape2);
+                self.add_with_material(shape1, material);
+                self.add_with_material(shape2, material);
+            }
+            Shape::Repeat {
+                shape,
+                center,
+                spacing,
+            } => {
+                self.0.push(ShapeGPU {
+                    pos: *center,
+                    id: 9,
+                    v1: *spacing,
+                    ..Default::default()
+                });
+                self.add_with_material(shape, material);
+            }
+            Shape::RepeatLimited {
+                shape,
+                center,
+                spacing,
+                count,
+            } => {
+                self.0.push(ShapeGPU {
+                    pos: *center,
+                    id: 10,
+                    v1: *spacing,
+                    v2: *count,
+                    ..Default::default()
+                });
+                self.add_with_material(shape, material);
+            }
+            Shape::Rotate { shape, rotation } => {
+                self.0.push(ShapeGPU {
+                    id: 11,
+                    m1: rotation.inverse(),
+                    ..Default::default()
+                });
+                self.add_with_material(shape, material);
+            }
+            Shape::RotateAround {
+                shape,
+                center,
+                rotation,
+            } => {
+                self.0.push(ShapeGPU {
+                    id: 23,
+                    v1: *center,
+                    m1: rotation.inverse(),
+                    ..Default::default()
+                });
+                self.add_with_material(shape, material);
+            }
+            Shape::Scale { shape, factor } => {
+                assert_scale_factor(*factor);
+                self.0.push(ShapeGPU {
+                    id: 12,
+                    f1: *factor,
+                    ..Default::default()
+                });
+                self.add_with_material(shape, material);
+            }
+            Shape::Bend {
+                shape,
+                center,
+                amount,
+            } => {
+                self.0.push(ShapeGPU {
+                    pos: *center,
+                    id: 13,
+                    f1: *amount,
+                    ..Default::default()
+                });
+                self.add_with_material(shape, material);
+            }
+            Shape::Onion { shape, thickness } => {
+                self.0.push(ShapeGPU {
+                    id: 14,
+                    f1: *thickness,
+                    ..Default::default()
+                });
+                self.add_with_material(shape, material);
             }
+            Shape::Orbit {
+                shape,
+                center,
+                radius,
+                speed,
+            } => {
+                self.0.push(ShapeGPU {
+                    id: 22,
+                    v1: *center,
+                    f1: *radius,
+                    f2: *speed,
+                    ..Default::default()
+                });
+                self.add_with_material(shape, material);
+            }
+            // f1 and f2 hold the first offset and the amount of offsets as u32 bits
+            Shape::Instances { shape, offsets } => {
+                assert!(
+                    shape.node_count() == 1,
+                    "instanced shapes must be a single primitive"
+                );
+                self.0.push(ShapeGPU {
+                    id: 24,
+                    f1: f32::from_bits(self.1.len() as u32),
+                    f2: f32::from_bits(offsets.len() as u32),
+                    ..Default::default()
+                });
+                self.1.extend_from_slice(offsets);
+                self.add_with_material(shape, material);
+            }
+            Shape::Visible { shape, visible } => {
+                self.0.push(ShapeGPU {
+                    id: 17,
+                    f1: if *visible { 1.0 } else { 0.0 },
+                    ..Default::default()
+                });
+                self.add_with_material(shape, material);
+            }
+            Shape::Material { shape, material } => self.add_with_material(shape, *material),
             Shape::Sphere { pos, radius } => self.0.push(ShapeGPU {
                 id: 6,
                 pos: *pos,
                 f1: *radius,
+                material: material.0,
                 ..Default::default()
             }),
             Shape::BoxExact { pos, b } => self.0.push(ShapeGPU {
                 pos: *pos,
                 id: 7,
                 v1: *b,
+                material: material.0,
                 ..Default::default()
             }),
             Shape::Plane { pos, normal } => self.0.push(ShapeGPU {
                 pos: *pos,
                 id: 8,
                 v1: *normal,
+                material: material.0,
+                ..Default::default()
+            }),
+            Shape::Disk {
+                pos,
+                normal,
+                radius,
+            } => self.0.push(ShapeGPU {
+                pos: *pos,
+                id: 16,
+                v1: normal.normalize(),
+                f1: *radius,
+                material: material.0,
+                ..Default::default()
+            }),
+            Shape::Mandelbulb {
+                pos,
+                power,
+                iterations,
+            } => self.0.push(ShapeGPU {
+                pos: *pos,
+                id: 15,
+                f1: *power,
+                f2: (*iterations).min(MAX_MANDELBULB_ITERATIONS) as f32,
+                material: material.0,
+                ..Default::default()
+            }),
+            Shape::MengerSponge {
+                pos,
+                size,
+                iterations,
+            } => self.0.push(ShapeGPU {
+                pos: *pos,
+                id: 18,
+                f1: *size,
+                f2: (*iterations).min(MAX_MENGER_ITERATIONS) as f32,
+                material: material.0,
+                ..Default::default()
+            }),
+            Shape::Pyramid { pos, base, height } => {
+                assert_pyramid_size(*base, *height);
+                self.0.push(ShapeGPU {
+                    pos: *pos,
+                    id: 20,
+                    v1: vec3(*height, 0.0, 0.0),
+                    f1: *base,
+                    material: material.0,
+                    ..Default::default()
+                })
+            }
+            Shape::Link {
+                pos,
+                length,
+                inner_radius,
+                tube_radius,
+            } => {
+                let (half_length, r1, r2) = link_params(*length, *inner_radius, *tube_radius);
+                self.0.push(ShapeGPU {
+                    pos: *pos,
+                    id: 21,
+                    v1: vec3(half_length, r1, 0.0),
+                    f1: r2,
+                    material: material.0,
+                    ..Default::default()
+                })
+            }
+            Shape::Custom { id, pos, v1, f1 } => self.0.push(ShapeGPU {
+                pos: *pos,
+                id: 19,
+                v1: *v1,
+                f1: *f1,
+                // Stored as bits since large ids do not fit into a float exactly
+                f2: f32::from_bits(*id),
+                material: material.0,
                 ..Default::default()
             }),
         };
     }
 }
 
-// ShaderType auto pads!
-// Try to minimize size
-#[derive(Debug, Clone, ShaderType)]
-pub(crate) struct Globals {
-    pub(crate) screen_dim: UVec2,
-    pub(crate) camera_pos: Vec3,
-    pub(crate) camera_rot: Mat3,
-    pub(crate) light_pos: Vec3,
-    pub(crate) focal_length: f32,
-    pub(crate) time: f32,
-    pub(crate) shape_amount: u32,
+/// Handle to a shape added with add_static_shape
+/// Handles of removed shapes are stale and ignored, even if the slot is reused
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ShapeHandle {
+    index: usize,
+    generation: u32,
 }
-impl RenderContext {
-    // Creating some of the wgpu types requires async code
-    pub(crate) async fn new(window: Window) -> Self {
-        // Init wpgu
-        let (surface, adapter, device, queue) = init_wpgu(&window).await;
 
-        // Configure surface
-        let surface_config =
-            create_surface_config(&window, &surface, &adapter, PresentMode::AutoVsync);
-        surface.configure(&device, &surface_config);
+struct StaticSlot {
+    generation: u32,
+    shape: Option<Shape>,
+    translation: Vec3,
+}
 
-        // Default global data
-        let globals = Globals {
-            camera_pos: Vec3::ZERO,
-            camera_rot: Mat3::from_rotation_y(0.0),
-            light_pos: vec3(-2.0, 2.0, -4.0),
-            screen_dim: uvec2(WIDTH, HEIGHT),
-            focal_length: 1.0,
-            time: 2.0,
-            shape_amount: 0,
-        };
-        dbg!(Globals::min_size());
-        dbg!(ShapeGPU::min_size());
+/// Shapes which are kept between frames
+/// Removed slots are reused by later shapes with a new generation
+#[derive(Default)]
+pub(crate) struct StaticShapes {
+    slots: Vec<StaticSlot>,
+    free: Vec<usize>,
+}
 
-        // let spheres = Vec::<ShapeGPU>::with_capacity(MAX_SHAPE_AMOUNT as usize);
+impl StaticShapes {
+    pub(crate) fn add(&mut self, shape: Shape) -> ShapeHandle {
+        match self.free.pop() {
+            Some(index) => {
+                let slot = &mut self.slots[index];
+                slot.shape = Some(shape);
+                slot.translation = Vec3::ZERO;
+                ShapeHandle {
+                    index,
+                    generation: slot.generation,
+                }
+            }
+            None => {
+                self.slots.push(StaticSlot {
+                    generation: 0,
+                    shape: Some(shape),
+                    translation: Vec3::ZERO,
+                });
+                ShapeHandle {
+                    index: self.slots.len() - 1,
+                    generation: 0,
+                }
+            }
+        }
+    }
 
-        let texture = device.create_texture(&wgpu::TextureDescriptor {
-            label: Some("texture desc"),
-            size: Extent3d {
-                width: WIDTH,
-                height: HEIGHT,
-                depth_or_array_layers: 1,
-            },
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Rgba8Unorm,
-            usage: wgpu::TextureUsages::STORAGE_BINDING
-                | wgpu::TextureUsages::COPY_DST
-                | wgpu::TextureUsages::TEXTURE_BINDING,
-            view_formats: &[],
-        });
-        let texture_view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    pub(crate) fn remove(&mut self, handle: ShapeHandle) -> Option<Shape> {
+        let slot = self.slot_mut(handle)?;
+        let shape = slot.shape.take();
+        slot.generation += 1;
+        self.free.push(handle.index);
+        shape
+    }
 
-        // Create compute pipeline
-        let (compute_pipeline, input_buffer, global_uniform_buffer, compute_bind_group) =
-            create_compute_pipeline(&device, &globals, &texture_view);
+    /// Replaces the shape, keeping its handle
+    /// Returns false if the handle is stale
+    pub(crate) fn set(&mut self, handle: ShapeHandle, shape: Shape) -> bool {
+        match self.slot_mut(handle) {
+            Some(slot) => {
+                slot.shape = Some(shape);
+                slot.translation = Vec3::ZERO;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Sets the translation relative to the position the shape was added at
+    /// Returns false if the handle is stale
+    pub(crate) fn set_translation(&mut self, handle: ShapeHandle, translation: Vec3) -> bool {
+        match self.slot_mut(handle) {
+            Some(StaticSlot {
+                shape: Some(shape),
+                translation: current,
+                ..
+            }) => {
+                shape.translate(translation - *current);
+                *current = translation;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub(crate) fn clear(&mut self) {
+        for (index, slot) in self.slots.iter_mut().enumerate() {
+            if slot.shape.take().is_some() {
+                slot.generation += 1;
+                self.free.push(index);
+            }
+        }
+    }
+
+    #[cfg(test)]
+    pub(crate) fn len(&self) -> usize {
+        self.slots.len() - self.free.len()
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = &Shape> {
+        self.slots.iter().filter_map(|slot| slot.shape.as_ref())
+    }
+
+    fn slot_mut(&mut self, handle: ShapeHandle) -> Option<&mut StaticSlot> {
+        self.slots
+            .get_mut(handle.index)
+            .filter(|slot| slot.generation == handle.generation && slot.shape.is_some())
+    }
+}
+
+/// Measures the gpu time of the compute pass with timestamp queries
+/// Results are read back without blocking, so they lag a frame or more behind
+pub(crate) struct GpuTimer {
+    query_set: wgpu::QuerySet,
+    read_buffer: Buffer,
+    // Set by the map callback once read_buffer can be read
+    mapped: Arc<AtomicBool>,
+    // A read of read_buffer is in flight
+    reading: bool,
+    // Nanoseconds per timestamp tick
+    period: f32,
+    pub(crate) last_ms: Option<f32>,
+}
+
+impl GpuTimer {
+    fn new(device: &Device, queue: &Queue) -> Self {
+        let size = 2 * std::mem::size_of::<u64>() as u64;
+        Self {
+            query_set: device.create_query_set(&wgpu::QuerySetDescriptor {
+                label: Some("compute timestamps"),
+                ty: wgpu::QueryType::Timestamp,
+                count: 2,
+            }),
+            read_buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("timestamp read buffer"),
+                size,
+                usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }),
+            mapped: Arc::new(AtomicBool::new(false)),
+            reading: false,
+            period: queue.get_timestamp_period(),
+            last_ms: None,
+        }
+    }
+
+    /// Reads back the previous timestamps if available
+    /// Returns true if the timer is free to record this frame
+    fn collect(&mut self, device: &Device) -> bool {
+        if !self.reading {
+            return true;
+        }
+        device.poll(wgpu::Maintain::Poll);
+        if !self.mapped.swap(false, Ordering::AcqRel) {
+            return false;
+        }
+        {
+            let data = self.read_buffer.slice(..).get_mapped_range();
+            let timestamps: &[u64] = bytemuck::cast_slice(&data);
+            let ticks = timestamps[1].saturating_sub(timestamps[0]);
+            self.last_ms = Some(ticks as f32 * self.period / 1_000_000.0);
+        }
+        self.read_buffer.unmap();
+        self.reading = false;
+        true
+    }
+
+    fn resolve(&self, encoder: &mut wgpu::CommandEncoder) {
+        encoder.write_timestamp(&self.query_set, 1);
+        encoder.resolve_query_set(&self.query_set, 0..2, &self.read_buffer, 0);
+    }
+
+    fn start_read(&mut self) {
+        let mapped = self.mapped.clone();
+        self.read_buffer
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |result| {
+                mapped.store(result.is_ok(), Ordering::Release);
+            });
+        self.reading = true;
+    }
+}
+
+/// Extracts pixels above the bloom threshold and blurs them in two separable compute passes
+/// The result is added over the image when presenting
+pub(crate) struct BloomPass {
+    blur_x_pipeline: ComputePipeline,
+    blur_y_pipeline: ComputePipeline,
+    blur_x_bind_group: BindGroup,
+    blur_y_bind_group: BindGroup,
+    pub(crate) view: TextureView,
+}
+
+impl BloomPass {
+    fn new(
+        device: &Device,
+        resolution: (u32, u32),
+        texture_view: &TextureView,
+        post_uniform_buffer: &Buffer,
+    ) -> Self {
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("bloom bind group layout"),
+            entries: &[
+                // Source, only loaded so no filtering
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    },
+                    count: None,
+                },
+                // Target
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::StorageTexture {
+                        access: wgpu::StorageTextureAccess::WriteOnly,
+                        format: wgpu::TextureFormat::Rgba16Float,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                    },
+                    count: None,
+                },
+                // Post globals
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("bloom pipeline layout"),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+        let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("bloom shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("../shaders/bloom_shader.wgsl").into()),
+        });
+        let create_pipeline = |entry_point| {
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("bloom pipeline"),
+                layout: Some(&pipeline_layout),
+                module: &shader_module,
+                entry_point,
+            })
+        };
+        let blur_x_pipeline = create_pipeline("blur_x");
+        let blur_y_pipeline = create_pipeline("blur_y");
+
+        // Bright pixels blurred horizontally, kept alive by the bind groups
+        let temp_view = create_bloom_texture(device, resolution);
+        let view = create_bloom_texture(device, resolution);
+        let create_bind_group = |source: &TextureView, target: &TextureView| {
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("bloom bind group"),
+                layout: &layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(source),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::TextureView(target),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: post_uniform_buffer.as_entire_binding(),
+                    },
+                ],
+            })
+        };
+        let blur_x_bind_group = create_bind_group(texture_view, &temp_view);
+        let blur_y_bind_group = create_bind_group(&temp_view, &view);
+
+        Self {
+            blur_x_pipeline,
+            blur_y_pipeline,
+            blur_x_bind_group,
+            blur_y_bind_group,
+            view,
+        }
+    }
+
+    fn execute(&self, encoder: &mut wgpu::CommandEncoder, resolution: (u32, u32)) {
+        let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("bloom pass"),
+        });
+        let workgroups_x = resolution.0.div_ceil(BLOOM_WORKGROUP_SIZE);
+        let workgroups_y = resolution.1.div_ceil(BLOOM_WORKGROUP_SIZE);
+        cpass.set_pipeline(&self.blur_x_pipeline);
+        cpass.set_bind_group(0, &self.blur_x_bind_group, &[]);
+        cpass.dispatch_workgroups(workgroups_x, workgroups_y, 1);
+        cpass.set_pipeline(&self.blur_y_pipeline);
+        cpass.set_bind_group(0, &self.blur_y_bind_group, &[]);
+        cpass.dispatch_workgroups(workgroups_x, workgroups_y, 1);
+    }
+}
+
+impl Globals {
+    /// Default global data for the given resolution
+    fn new((width, height): (u32, u32)) -> Self {
+        Globals {
+            camera_pos: Vec3::ZERO,
+            camera_rot: Mat3::from_rotation_y(0.0),
+            light_pos: vec3(-2.0, 2.0, -4.0),
+            screen_dim: uvec2(width, height),
+            focal_length: 1.0,
+            time: 2.0,
+            shape_amount: 0,
+            max_steps: MAX_STEPS,
+            max_dist: MAX_DIST,
+            surface_dist: SURFACE_DIST,
+            max_bounces: 1,
+            gamma_correction: 1,
+            light_dir: vec3(0.0, -1.0, 0.0),
+            light_type: POINT_LIGHT,
+            light_color: Vec3::ONE,
+            debug_mode: DebugMode::None as u32,
+            checker_enabled: 0,
+            checker_scale: 1.0,
+            checker_color_a: Vec3::ONE,
+            checker_color_b: Vec3::splat(0.2),
+            fog_color: Vec3::ZERO,
+            fog_density: 0.0,
+            aa_samples: 1,
+            grid_enabled: 0,
+            grid_spacing: 1.0,
+            grid_color: Vec3::splat(0.5),
+            tonemap: Tonemap::None as u32,
+            exposure: 1.0,
+            vignette_strength: 0.0,
+            vignette_radius: 0.5,
+            frame_index: 0,
+            normal_epsilon: 0.00001,
+            rim_color: Vec3::ZERO,
+            rim_power: 3.0,
+            max_internal_reflections: 4,
+            shadow_bias: 0.005,
+            environment_enabled: 0,
+            seed: 0,
+            ambient_color: Vec3::splat(0.05),
+        }
+    }
+}
+impl RenderContext {
+    // Creating some of the wgpu types requires async code
+    // Runs headless without a surface if window is None
+    pub(crate) async fn new(
+        window: Option<Window>,
+        resolution: (u32, u32),
+        config: &RunConfig,
+    ) -> Result<Self, RaymarcherError> {
+        let (width, height) = resolution;
+
+        // Init wpgu
+        let (surface, adapter, device, queue) = init_wpgu(window.as_ref(), config).await?;
+
+        // Window size is the render resolution when running headless
+        let window_size = window
+            .as_ref()
+            .map(|window| window.inner_size())
+            .unwrap_or(PhysicalSize::new(width, height));
+
+        // Configure surface
+        let surface_config = create_surface_config(
+            window_size,
+            surface.as_ref(),
+            &adapter,
+            PresentMode::AutoVsync,
+        );
+        if let Some(surface) = &surface {
+            surface.configure(&device, &surface_config);
+        }
+
+        let globals = Globals::new(resolution);
+        dbg!(Globals::min_size());
+        dbg!(ShapeGPU::min_size());
+
+        let max_shape_capacity = max_shape_capacity(&device.limits());
+        log::info!("shape buffer holds at most {max_shape_capacity} shapes");
+
+        // let spheres = Vec::<ShapeGPU>::with_capacity(INITIAL_SHAPE_CAPACITY as usize);
+
+        let (texture, texture_view) = create_texture(&device, resolution);
+        let (depth_texture, depth_texture_view) = create_depth_texture(&device, resolution);
+        let (normal_texture, normal_texture_view) = create_normal_texture(&device, resolution);
+        let (id_texture, id_texture_view) = create_id_texture(&device, resolution);
+        let accumulation_buffer = create_accumulation_buffer(&device, resolution);
+        let instance_buffer = create_instance_buffer(&device, INITIAL_INSTANCE_CAPACITY);
+        let (surface_texture, surface_texture_view) =
+            create_image_texture(&device, &queue, "surface texture", 1, 1, &[255; 4]);
+        let surface_sampler = create_surface_sampler(&device);
+        let (environment_texture, environment_texture_view) = create_image_texture(
+            &device,
+            &queue,
+            "environment texture",
+            1,
+            1,
+            &[0, 0, 0, 255],
+        );
+        let environment_sampler = create_environment_sampler(&device);
+
+        // Create compute pipeline
+        if !workgroup_size_supported(config.workgroup_size, &device.limits()) {
+            return Err(RaymarcherError::InvalidWorkgroupSize(config.workgroup_size));
+        }
+        // Catches compile errors of the custom sdf instead of panicking
+        device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let (
+            compute_pipeline,
+            input_buffer,
+            global_uniform_buffer,
+            material_buffer,
+            compute_bind_group,
+        ) = create_compute_pipeline(
+            &device,
+            &globals,
+            &texture_view,
+            &depth_texture_view,
+            &normal_texture_view,
+            &id_texture_view,
+            &accumulation_buffer,
+            &surface_texture_view,
+            &surface_sampler,
+            &environment_texture_view,
+            &environment_sampler,
+            &instance_buffer,
+            config.workgroup_size,
+            config.custom_sdf.as_deref(),
+        );
+        if let Some(err) = device.pop_error_scope().await {
+            return Err(RaymarcherError::ShaderCompilationFailed(err));
+        }
 
         // Create render pipeline
-        let (render_pipeline, texture_bind_group) =
-            create_render_pipeline(&device, &surface_config, &texture_view);
+        let texture_filter = wgpu::FilterMode::Linear;
+        let post_globals = PostGlobals::default();
+        let post_uniform_buffer = create_post_uniform_buffer(&device, &post_globals);
+        let bloom = BloomPass::new(&device, resolution, &texture_view, &post_uniform_buffer);
+        let (render_pipeline, texture_bind_group) = create_render_pipeline(
+            &device,
+            &surface_config,
+            &post_uniform_buffer,
+            &texture_view,
+            &depth_texture_view,
+            &bloom.view,
+            texture_filter,
+        );
 
         // Vertex and index buffer
         let (vertex_buffer, index_buffer, num_indices) = create_vertex_index_buffers(&device);
 
-        let window_size = window.inner_size();
+        let shapes = Vec::with_capacity(INITIAL_SHAPE_CAPACITY as usize);
 
-        let shapes = Vec::with_capacity(MAX_SHAPE_AMOUNT as usize);
+        let gpu_timer = device
+            .features()
+            .contains(wgpu::Features::TIMESTAMP_QUERY)
+            .then(|| GpuTimer::new(&device, &queue));
 
-        Self {
+        let mut render_ctx = Self {
             window,
             surface,
             device,
@@ -223,23 +1849,82 @@ impl RenderContext {
             input_buffer,
             global_uniform_buffer,
             compute_bind_group,
+            texture,
             texture_view,
+            depth_texture,
+            depth_texture_view,
+            normal_texture,
+            normal_texture_view,
+            id_texture,
+            id_texture_view,
+            accumulation_buffer,
+            accumulating: false,
+            accumulated_frames: 0,
+            culling: false,
 
             render_pipeline,
             vertex_buffer,
             index_buffer,
             num_indices,
             texture_bind_group,
+            post_uniform_buffer,
+            bloom,
+            post_globals,
 
             globals,
-            resolution: (WIDTH, HEIGHT),
+            resolution,
             shapes,
-        }
+            static_shapes: StaticShapes::default(),
+            material_buffer,
+            materials: vec![Material::default()],
+            render_scale: 1.0,
+            texture_filter,
+            clear_color: wgpu::Color::BLACK,
+            gpu_timer,
+            shape_capacity: INITIAL_SHAPE_CAPACITY,
+            max_shape_capacity,
+            shapes_dropped: false,
+            instance_buffer,
+            instance_capacity: INITIAL_INSTANCE_CAPACITY,
+            workgroup_size: config.workgroup_size,
+            surface_texture,
+            surface_texture_view,
+            surface_sampler,
+            environment_texture,
+            environment_texture_view,
+            environment_sampler,
+        };
+        render_ctx.update_material_buffer();
+
+        Ok(render_ctx)
     }
 
+    /// Falls back to AutoVsync if the present mode is not supported by the surface
     pub(crate) fn reconfigure_present_mode(&mut self, present_mode: PresentMode) {
+        let supported = matches!(
+            present_mode,
+            PresentMode::AutoVsync | PresentMode::AutoNoVsync
+        ) || self.surface.as_ref().is_none_or(|surface| {
+            surface
+                .get_capabilities(&self.adapter)
+                .present_modes
+                .contains(&present_mode)
+        });
+        let present_mode = if supported {
+            present_mode
+        } else {
+            log::warn!(
+                "present mode {:?} is not supported, falling back to {:?}",
+                present_mode,
+                PresentMode::AutoVsync
+            );
+            PresentMode::AutoVsync
+        };
+
         self.surface_config.present_mode = present_mode;
-        self.surface.configure(&self.device, &self.surface_config);
+        if let Some(surface) = &self.surface {
+            surface.configure(&self.device, &self.surface_config);
+        }
     }
 
     pub(crate) fn resize_window(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
@@ -247,21 +1932,193 @@ impl RenderContext {
             self.window_size = new_size;
             self.surface_config.width = new_size.width;
             self.surface_config.height = new_size.height;
-            self.surface.configure(&self.device, &self.surface_config);
+            if let Some(surface) = &self.surface {
+                surface.configure(&self.device, &self.surface_config);
+            }
+            // Render resolution follows the window
+            self.set_resolution(self.scaled_window_size());
+        }
+    }
+
+    /// Renders at scale times the window size, the result is stretched to fill the window
+    pub(crate) fn set_render_scale(&mut self, scale: f32) {
+        self.render_scale = scale;
+        self.set_resolution(self.scaled_window_size());
+    }
+
+    /// Recreates the texture bind group with a sampler using filter
+    pub(crate) fn set_texture_filter(&mut self, filter: wgpu::FilterMode) {
+        self.texture_filter = filter;
+        self.texture_bind_group = create_texture_bind_group(
+            &self.device,
+            &self.render_pipeline.get_bind_group_layout(0),
+            &self.texture_view,
+            &self.depth_texture_view,
+            &self.bloom.view,
+            &self.post_uniform_buffer,
+            filter,
+        );
+    }
+
+    pub(crate) fn update_post_uniforms(&mut self) {
+        let mut buffer = UniformBuffer::new(Vec::new());
+        buffer.write(&self.post_globals).unwrap();
+        let byte_buffer = buffer.into_inner();
+        self.queue
+            .write_buffer(&self.post_uniform_buffer, 0, &byte_buffer);
+    }
+
+    fn scaled_window_size(&self) -> (u32, u32) {
+        let width = (self.window_size.width as f32 * self.render_scale).round() as u32;
+        let height = (self.window_size.height as f32 * self.render_scale).round() as u32;
+        (width.max(1), height.max(1))
+    }
+
+    /// Recreates the render texture and the bind groups using it
+    pub(crate) fn set_resolution(&mut self, resolution: (u32, u32)) {
+        if resolution == self.resolution {
+            return;
         }
+        self.resolution = resolution;
+        self.globals.screen_dim = uvec2(resolution.0, resolution.1);
+
+        (self.texture, self.texture_view) = create_texture(&self.device, resolution);
+        (self.depth_texture, self.depth_texture_view) =
+            create_depth_texture(&self.device, resolution);
+        (self.normal_texture, self.normal_texture_view) =
+            create_normal_texture(&self.device, resolution);
+        (self.id_texture, self.id_texture_view) = create_id_texture(&self.device, resolution);
+        self.accumulation_buffer = create_accumulation_buffer(&self.device, resolution);
+        self.accumulated_frames = 0;
+        self.rebuild_compute_bind_group();
+        self.bloom = BloomPass::new(
+            &self.device,
+            resolution,
+            &self.texture_view,
+            &self.post_uniform_buffer,
+        );
+        self.texture_bind_group = create_texture_bind_group(
+            &self.device,
+            &self.render_pipeline.get_bind_group_layout(0),
+            &self.texture_view,
+            &self.depth_texture_view,
+            &self.bloom.view,
+            &self.post_uniform_buffer,
+            self.texture_filter,
+        );
+    }
+
+    /// Enables/Disables averaging frames, disabling also resets the average
+    pub(crate) fn set_accumulation(&mut self, enabled: bool) {
+        self.accumulating = enabled;
+        self.accumulated_frames = 0;
+    }
+
+    /// Recreates the compute bind group after any of its resources changed
+    fn rebuild_compute_bind_group(&mut self) {
+        self.compute_bind_group = create_compute_bind_group(
+            &self.device,
+            &self.compute_pipeline.get_bind_group_layout(0),
+            &self.input_buffer,
+            &self.global_uniform_buffer,
+            &self.material_buffer,
+            &self.texture_view,
+            &self.depth_texture_view,
+            &self.normal_texture_view,
+            &self.id_texture_view,
+            &self.accumulation_buffer,
+            &self.surface_texture_view,
+            &self.surface_sampler,
+            &self.environment_texture_view,
+            &self.environment_sampler,
+            &self.instance_buffer,
+        );
+    }
+
+    /// Replaces the texture sampled by textured materials
+    /// rgba: 8 bit srgb rgba pixels, row by row
+    pub(crate) fn set_surface_texture(&mut self, width: u32, height: u32, rgba: &[u8]) {
+        let (texture, view) = create_image_texture(
+            &self.device,
+            &self.queue,
+            "surface texture",
+            width,
+            height,
+            rgba,
+        );
+        self.surface_texture = texture;
+        self.surface_texture_view = view;
+        self.rebuild_compute_bind_group();
+    }
+
+    /// Replaces the equirectangular image sampled by missed rays and enables it
+    /// rgba: 8 bit srgb rgba pixels, row by row
+    pub(crate) fn set_environment_texture(&mut self, width: u32, height: u32, rgba: &[u8]) {
+        let (texture, view) = create_image_texture(
+            &self.device,
+            &self.queue,
+            "environment texture",
+            width,
+            height,
+            rgba,
+        );
+        self.environment_texture = texture;
+        self.environment_texture_view = view;
+        self.globals.environment_enabled = 1;
+        self.rebuild_compute_bind_group();
     }
 
     fn execute_raymarch(&mut self, time_ctx: &TimeContext) {
-        self.update_global_uniforms(time_ctx, self.shapes.len() as u32);
-        self.update_input_buffer(shapes_to_gpu(&self.shapes));
+        // Indices are kept from before culling so the id texture matches pick
+        let mut shapes: Vec<(usize, &Shape)> = self
+            .all_shapes()
+            .enumerate()
+            .filter(|(_, shape)| !self.culling || !self.culled(shape))
+            .collect();
+        let fitting = shapes_within_capacity(
+            shapes.iter().map(|(_, shape)| *shape),
+            self.max_shape_capacity,
+        );
+        let dropped = shapes.len() - fitting;
+        shapes.truncate(fitting);
+        let len = shapes.len() as u32;
+        let gpu_shapes = indexed_shapes_to_gpu(shapes);
+        if dropped > 0 && !self.shapes_dropped {
+            log::warn!(
+                "shapes exceed the {} the device can bind, skipping {dropped} shapes",
+                self.max_shape_capacity
+            );
+        }
+        self.shapes_dropped = dropped > 0;
+        self.update_global_uniforms(time_ctx, len);
+        self.update_input_buffer(gpu_shapes);
         self.execute_compute();
         self.shapes.clear();
     }
 
+    /// Returns true if no ray of the frame can reach shape, see culled
+    fn culled(&self, shape: &Shape) -> bool {
+        let g = &self.globals;
+        // Reflected and refracted rays leave the view in any direction
+        let scattering = self.materials.iter().any(|material| {
+            (material.reflectivity > 0.0 && g.max_bounces > 0) || material.alpha < 1.0
+        });
+        culled(g, scattering, shape.bounds())
+    }
+
+    /// Returns the shapes submitted this frame followed by the static shapes
+    pub(crate) fn all_shapes(&self) -> impl Iterator<Item = &Shape> {
+        self.shapes.iter().chain(self.static_shapes.iter())
+    }
+
     fn update_global_uniforms(&mut self, time_ctx: &TimeContext, len: u32) {
         // Update fields
-        self.globals.time = time_ctx.time_since_start();
+        self.globals.time = time_ctx.scaled_time;
         self.globals.shape_amount = len;
+        if self.accumulating {
+            self.accumulated_frames = self.accumulated_frames.saturating_add(1);
+        }
+        self.globals.frame_index = self.accumulated_frames;
 
         // Update buffer
         let mut buffer = UniformBuffer::new(Vec::new());
@@ -271,16 +2128,84 @@ impl RenderContext {
             .write_buffer(&self.global_uniform_buffer, 0, &byte_buffer);
     }
 
+    /// Adds material to the material table
+    pub(crate) fn add_material(&mut self, material: Material) -> MaterialId {
+        let id = MaterialId(self.materials.len() as u32);
+        self.materials.push(material);
+        self.update_material_buffer();
+        id
+    }
+
+    fn update_material_buffer(&mut self) {
+        let mut byte_buffer = Vec::new();
+        let mut buffer = StorageBuffer::new(&mut byte_buffer);
+        buffer.write(&self.materials).unwrap();
+        self.queue
+            .write_buffer(&self.material_buffer, 0, &byte_buffer);
+    }
+
     fn update_input_buffer(&mut self, shapes: ShapesGPU) {
         // dbg!(&shapes);
         // self.spheres[0].pos += vec3(0.0, 0.1, 0.0);
+        let len = shapes.0.len() as u64;
+        if len > self.shape_capacity {
+            self.grow_shape_buffer(len.next_power_of_two().min(self.max_shape_capacity));
+        }
+
         let mut byte_buffer = Vec::new();
         let mut buffer = StorageBuffer::new(&mut byte_buffer);
         buffer.write(&shapes.0).unwrap();
         self.queue.write_buffer(&self.input_buffer, 0, &byte_buffer);
+
+        self.update_instance_buffer(shapes.1);
+    }
+
+    fn update_instance_buffer(&mut self, mut offsets: Vec<Vec3>) {
+        let max_capacity = max_binding_size(&self.device.limits()) / INSTANCE_STRIDE;
+        let len = offsets.len() as u64;
+        if len > self.instance_capacity {
+            self.grow_instance_buffer(len.next_power_of_two().min(max_capacity));
+        }
+        // The shader skips offsets past the end of the buffer
+        if len > max_capacity {
+            log::warn!("instances exceed the {max_capacity} the device can bind");
+            offsets.truncate(max_capacity as usize);
+        }
+        if offsets.is_empty() {
+            return;
+        }
+
+        let mut byte_buffer = Vec::new();
+        let mut buffer = StorageBuffer::new(&mut byte_buffer);
+        buffer.write(&offsets).unwrap();
+        self.queue
+            .write_buffer(&self.instance_buffer, 0, &byte_buffer);
+    }
+
+    /// Recreates the input buffer with room for capacity flattened shapes
+    fn grow_shape_buffer(&mut self, capacity: u64) {
+        log::info!("growing shape buffer to {capacity} shapes");
+        self.shape_capacity = capacity;
+        self.input_buffer = create_shape_buffer(&self.device, capacity);
+        self.rebuild_compute_bind_group();
+    }
+
+    /// Recreates the instance buffer with room for capacity offsets
+    fn grow_instance_buffer(&mut self, capacity: u64) {
+        log::info!("growing instance buffer to {capacity} instances");
+        self.instance_capacity = capacity;
+        self.instance_buffer = create_instance_buffer(&self.device, capacity);
+        self.rebuild_compute_bind_group();
     }
 
     fn execute_compute(&mut self) {
+        // Timing is skipped while the previous result is still being read back
+        let device = &self.device;
+        let timer = self
+            .gpu_timer
+            .as_mut()
+            .and_then(|timer| timer.collect(device).then_some(timer));
+
         // Execute compute pass
         let mut encoder = self
             .device
@@ -288,24 +2213,148 @@ impl RenderContext {
                 label: Some("compute encoder"),
             });
 
+        if let Some(timer) = &timer {
+            encoder.write_timestamp(&timer.query_set, 0);
+        }
         {
             let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
                 label: Some("compute pass"),
             });
             cpass.set_bind_group(0, &self.compute_bind_group, &[]);
             cpass.set_pipeline(&self.compute_pipeline);
-            cpass.dispatch_workgroups(WIDTH, HEIGHT, 1);
+            cpass.dispatch_workgroups(
+                self.resolution.0.div_ceil(self.workgroup_size.0),
+                self.resolution.1.div_ceil(self.workgroup_size.1),
+                1,
+            );
+        }
+        if let Some(timer) = &timer {
+            timer.resolve(&mut encoder);
+        }
+
+        self.queue.submit(Some(encoder.finish()));
+        if let Some(timer) = timer {
+            timer.start_read();
         }
+    }
+
+    /// Casts a ray through pixel (px, py) using the same camera math as the compute shader
+    /// Returns the index of the first shape hit in the shapes submitted this frame
+    /// Static shapes are indexed after the shapes submitted this frame
+    pub(crate) fn pick(&self, px: u32, py: u32) -> Option<u32> {
+        let g = &self.globals;
+        let uv = vec2(
+            px as f32 / g.screen_dim.x as f32 * 2.0 - 1.0,
+            (1.0 - py as f32 / g.screen_dim.y as f32) * 2.0 - 1.0,
+        );
+        let ro = g.camera_pos;
+        let rd = (g.camera_rot * vec3(uv.x, uv.y, g.focal_length)).normalize();
+
+        let mut t = 0.0;
+        for _ in 0..g.max_steps {
+            let pos = ro + rd * t;
+            let (index, dist) = self
+                .all_shapes()
+                .enumerate()
+                .map(|(i, shape)| (i, shape.dist(pos)))
+                .min_by(|a, b| a.1.total_cmp(&b.1))?;
+
+            t += dist;
+
+            if dist < g.surface_dist {
+                return (t < g.max_dist).then_some(index as u32);
+            }
+            if t > g.max_dist {
+                break;
+            }
+        }
+        None
+    }
+
+    /// Copies the content of a texture into a mappable buffer and reads it back
+    /// Returns the bytes in row-major order with the row padding removed
+    pub(crate) fn read_texture(&self, texture: &wgpu::Texture, bytes_per_pixel: u32) -> Vec<u8> {
+        let size = (texture.width(), texture.height());
+        self.read_texture_region(texture, (0, 0), size, bytes_per_pixel)
+    }
+
+    /// Reads back the width x height pixels starting at origin of a texture
+    /// Returns the bytes in row-major order with the row padding removed
+    pub(crate) fn read_texture_region(
+        &self,
+        texture: &wgpu::Texture,
+        (x, y): (u32, u32),
+        (width, height): (u32, u32),
+        bytes_per_pixel: u32,
+    ) -> Vec<u8> {
+        let unpadded_bytes_per_row = width * bytes_per_pixel;
+        let padded_bytes_per_row = padded_bytes_per_row(width, bytes_per_pixel);
 
+        let buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("readback buffer"),
+            size: (padded_bytes_per_row * height) as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("readback encoder"),
+            });
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                origin: wgpu::Origin3d { x, y, z: 0 },
+                ..texture.as_image_copy()
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: NonZeroU32::new(padded_bytes_per_row),
+                    rows_per_image: NonZeroU32::new(height),
+                },
+            },
+            Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+        );
         self.queue.submit(Some(encoder.finish()));
+
+        // Wait for the copy to finish and map the buffer
+        let slice = buffer.slice(..);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            sender.send(result).unwrap();
+        });
+        self.device.poll(wgpu::Maintain::Wait);
+        receiver.recv().unwrap().unwrap();
+
+        // Remove row padding
+        let data = slice.get_mapped_range();
+        let mut bytes = Vec::with_capacity((unpadded_bytes_per_row * height) as usize);
+        for row in data.chunks(padded_bytes_per_row as usize) {
+            bytes.extend_from_slice(&row[..unpadded_bytes_per_row as usize]);
+        }
+        drop(data);
+        buffer.unmap();
+
+        bytes
     }
 
     pub(crate) fn render(&mut self, time_ctx: &TimeContext) -> Result<(), wgpu::SurfaceError> {
         // Execute raymarching compute shader
         self.execute_raymarch(time_ctx);
 
+        // Nothing to present to when running headless
+        let Some(surface) = &self.surface else {
+            return Ok(());
+        };
+
         // Render texture;
-        let output = self.surface.get_current_texture()?;
+        let output = surface.get_current_texture()?;
         let view = output
             .texture
             .create_view(&wgpu::TextureViewDescriptor::default());
@@ -314,6 +2363,9 @@ impl RenderContext {
             .create_command_encoder(&wgpu::CommandEncoderDescriptor {
                 label: Some("render encoder"),
             });
+        if self.post_globals.bloom_intensity > 0.0 {
+            self.bloom.execute(&mut encoder, self.resolution);
+        }
         {
             let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                 label: Some("render pass"),
@@ -321,7 +2373,7 @@ impl RenderContext {
                     view: &view,
                     resolve_target: None,
                     ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        load: wgpu::LoadOp::Clear(self.clear_color),
                         store: true,
                     },
                 })],
@@ -341,44 +2393,86 @@ impl RenderContext {
     }
 }
 
-async fn init_wpgu(window: &Window) -> (Surface, Adapter, Device, Queue) {
+/// Returns the camera rotation looking along forward
+/// Columns are the right, up and forward axes of the left handed camera space
+/// Picks an alternate up vector if forward is parallel to up
+pub(crate) fn look_at_rotation(forward: Vec3, up: Vec3) -> Mat3 {
+    let forward = forward.normalize();
+    let mut right = up.cross(forward);
+    if right.length_squared() < 1e-6 {
+        let alternate_up = if forward.y.abs() < 0.9 {
+            Vec3::Y
+        } else {
+            Vec3::Z
+        };
+        right = alternate_up.cross(forward);
+    }
+    let right = right.normalize();
+    let up = forward.cross(right);
+    Mat3::from_cols(right, up, forward)
+}
+
+/// Returns the bytes per row of a texture copy padded to the required alignment
+pub(crate) fn padded_bytes_per_row(width: u32, bytes_per_pixel: u32) -> u32 {
+    let unpadded = width * bytes_per_pixel;
+    let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+    unpadded.div_ceil(align) * align
+}
+
+async fn init_wpgu(
+    window: Option<&Window>,
+    config: &RunConfig,
+) -> Result<(Option<Surface>, Adapter, Device, Queue), RaymarcherError> {
     // Create surface
     let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
-        backends: wgpu::Backends::all(),
+        backends: config.backends,
         dx12_shader_compiler: Default::default(),
     });
-    let surface = unsafe { instance.create_surface(&window) }.unwrap();
+    let surface = window
+        .map(|window| unsafe { instance.create_surface(window) })
+        .transpose()?;
 
     // Create adapter. device and queue
     let adapter = instance
         .request_adapter(&wgpu::RequestAdapterOptions {
-            power_preference: wgpu::PowerPreference::default(),
-            compatible_surface: Some(&surface),
-            force_fallback_adapter: false,
+            power_preference: config.power_preference,
+            compatible_surface: surface.as_ref(),
+            force_fallback_adapter: config.force_fallback,
         })
         .await
-        .unwrap();
+        .ok_or(RaymarcherError::AdapterNotFound)?;
     let (device, queue) = adapter
         .request_device(
             &wgpu::DeviceDescriptor {
-                features: wgpu::Features::empty(),
+                // Optional, compute pass timing is unavailable without it
+                features: adapter.features() & wgpu::Features::TIMESTAMP_QUERY,
                 limits: wgpu::Limits::default(),
                 label: None,
             },
             None, // Trace path
         )
-        .await
-        .unwrap();
-    (surface, adapter, device, queue)
+        .await?;
+    Ok((surface, adapter, device, queue))
 }
 
+// Without a surface the config only describes the presentation size and format
 fn create_surface_config(
-    window: &Window,
-    surface: &Surface,
+    size: PhysicalSize<u32>,
+    surface: Option<&Surface>,
     adapter: &Adapter,
     present_mode: PresentMode,
 ) -> SurfaceConfiguration {
-    let size = window.inner_size();
+    let Some(surface) = surface else {
+        return wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            width: size.width,
+            height: size.height,
+            present_mode,
+            alpha_mode: wgpu::CompositeAlphaMode::Opaque,
+            view_formats: vec![],
+        };
+    };
     let surface_caps = surface.get_capabilities(adapter);
     let surface_format: wgpu::TextureFormat = surface_caps
         .formats
@@ -399,14 +2493,35 @@ fn create_surface_config(
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn create_compute_pipeline(
     device: &Device,
     globals: &Globals,
     texture_view: &TextureView,
-) -> (ComputePipeline, Buffer, Buffer, BindGroup) {
+    depth_texture_view: &TextureView,
+    normal_texture_view: &TextureView,
+    id_texture_view: &TextureView,
+    accumulation_buffer: &Buffer,
+    surface_texture_view: &TextureView,
+    surface_sampler: &wgpu::Sampler,
+    environment_texture_view: &TextureView,
+    environment_sampler: &wgpu::Sampler,
+    instance_buffer: &Buffer,
+    workgroup_size: (u32, u32),
+    custom_sdf: Option<&str>,
+) -> (ComputePipeline, Buffer, Buffer, Buffer, BindGroup) {
+    let custom_sdf = custom_sdf.unwrap_or(include_str!("../shaders/custom_sdf.wgsl"));
+    let source = format!(
+        "{}\n{}",
+        with_workgroup_size(
+            include_str!("../shaders/compute_shader.wgsl"),
+            workgroup_size
+        ),
+        custom_sdf
+    );
     let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
         label: Some("compute shader"),
-        source: wgpu::ShaderSource::Wgsl(include_str!("../shaders/compute_shader.wgsl").into()),
+        source: wgpu::ShaderSource::Wgsl(source.into()),
     });
 
     let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
@@ -441,56 +2556,157 @@ fn create_compute_pipeline(
                 visibility: wgpu::ShaderStages::COMPUTE,
                 ty: wgpu::BindingType::StorageTexture {
                     access: wgpu::StorageTextureAccess::WriteOnly,
-                    format: wgpu::TextureFormat::Rgba8Unorm, // TODO SRGB?
+                    format: wgpu::TextureFormat::Rgba8Unorm, // Srgb formats can not be used for storage
                     view_dimension: wgpu::TextureViewDimension::D2,
                 },
                 count: None,
             },
-        ],
-    });
-
-    let buffer_size = u64::from(ShapeGPU::min_size()) * MAX_SHAPE_AMOUNT;
-    let input_buffer = device.create_buffer(&wgpu::BufferDescriptor {
-        label: Some("shape buffer"),
-        size: buffer_size,
-        usage: wgpu::BufferUsages::STORAGE
-            | wgpu::BufferUsages::COPY_DST
-            | wgpu::BufferUsages::COPY_SRC,
-        mapped_at_creation: false,
-    });
-
-    // Globals unfiform
-    let mut buffer = UniformBuffer::new(Vec::new());
-    buffer.write(&globals).unwrap();
-    let byte_buffer = buffer.into_inner();
-
-    let global_uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-        label: Some("global uniform buffer"),
-        contents: &byte_buffer,
-        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-        // contents: bytemuck::cast_slice(&[globals]),
-    });
-
-    // Bind group
-    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-        label: Some("compute bind group"),
-        layout: &bind_group_layout,
-        entries: &[
-            wgpu::BindGroupEntry {
-                binding: 0,
-                resource: input_buffer.as_entire_binding(),
-            },
-            wgpu::BindGroupEntry {
-                binding: 1,
-                resource: global_uniform_buffer.as_entire_binding(),
+            // Material table
+            wgpu::BindGroupLayoutEntry {
+                binding: 3,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: true },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
             },
-            wgpu::BindGroupEntry {
-                binding: 2,
-                resource: wgpu::BindingResource::TextureView(texture_view),
+            // Depth texture
+            wgpu::BindGroupLayoutEntry {
+                binding: 4,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::StorageTexture {
+                    access: wgpu::StorageTextureAccess::WriteOnly,
+                    format: wgpu::TextureFormat::R32Float,
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                },
+                count: None,
+            },
+            // Normal texture
+            wgpu::BindGroupLayoutEntry {
+                binding: 5,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::StorageTexture {
+                    access: wgpu::StorageTextureAccess::WriteOnly,
+                    format: wgpu::TextureFormat::Rgba16Float,
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                },
+                count: None,
+            },
+            // Accumulated colors
+            wgpu::BindGroupLayoutEntry {
+                binding: 6,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: false },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            // Surface texture
+            wgpu::BindGroupLayoutEntry {
+                binding: 7,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Texture {
+                    multisampled: false,
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                },
+                count: None,
+            },
+            // Surface sampler
+            wgpu::BindGroupLayoutEntry {
+                binding: 8,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                count: None,
+            },
+            // Environment texture
+            wgpu::BindGroupLayoutEntry {
+                binding: 9,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Texture {
+                    multisampled: false,
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                },
+                count: None,
+            },
+            // Environment sampler
+            wgpu::BindGroupLayoutEntry {
+                binding: 10,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                count: None,
+            },
+            // Instance offsets
+            wgpu::BindGroupLayoutEntry {
+                binding: 11,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: true },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            // Shape id texture
+            wgpu::BindGroupLayoutEntry {
+                binding: 12,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::StorageTexture {
+                    access: wgpu::StorageTextureAccess::WriteOnly,
+                    format: wgpu::TextureFormat::R32Uint,
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                },
+                count: None,
             },
         ],
     });
 
+    let input_buffer = create_shape_buffer(device, INITIAL_SHAPE_CAPACITY);
+
+    // Globals unfiform
+    let mut buffer = UniformBuffer::new(Vec::new());
+    buffer.write(&globals).unwrap();
+    let byte_buffer = buffer.into_inner();
+
+    let global_uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("global uniform buffer"),
+        contents: &byte_buffer,
+        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        // contents: bytemuck::cast_slice(&[globals]),
+    });
+
+    // Material table, sized for the maximum amount of materials
+    let material_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("material buffer"),
+        size: u64::from(Material::min_size()) * MAX_MATERIAL_AMOUNT,
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+
+    // Bind group
+    let bind_group = create_compute_bind_group(
+        device,
+        &bind_group_layout,
+        &input_buffer,
+        &global_uniform_buffer,
+        &material_buffer,
+        texture_view,
+        depth_texture_view,
+        normal_texture_view,
+        id_texture_view,
+        accumulation_buffer,
+        surface_texture_view,
+        surface_sampler,
+        environment_texture_view,
+        environment_sampler,
+        instance_buffer,
+    );
+
     let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
         label: Some("compute pipeline layout"),
         bind_group_layouts: &[&bind_group_layout],
@@ -504,24 +2720,377 @@ fn create_compute_pipeline(
         entry_point: "cs_main",
     });
 
-    (pipeline, input_buffer, global_uniform_buffer, bind_group)
+    (
+        pipeline,
+        input_buffer,
+        global_uniform_buffer,
+        material_buffer,
+        bind_group,
+    )
 }
 
-fn create_render_pipeline(
+/// Whether the device can run compute workgroups of the given size
+fn workgroup_size_supported((x, y): (u32, u32), limits: &wgpu::Limits) -> bool {
+    x > 0
+        && y > 0
+        && x <= limits.max_compute_workgroup_size_x
+        && y <= limits.max_compute_workgroup_size_y
+        && x.checked_mul(y)
+            .is_some_and(|threads| threads <= limits.max_compute_invocations_per_workgroup)
+}
+
+/// Sets the workgroup size of the compute shader entry point
+fn with_workgroup_size(source: &str, (x, y): (u32, u32)) -> String {
+    let default = "@workgroup_size(1)";
+    debug_assert_eq!(source.matches(default).count(), 1);
+    source.replacen(default, &format!("@workgroup_size({x}, {y}, 1)"), 1)
+}
+
+/// Returns the size in bytes of the largest storage buffer which can be bound
+fn max_binding_size(limits: &wgpu::Limits) -> u64 {
+    u64::from(limits.max_storage_buffer_binding_size).min(limits.max_buffer_size)
+}
+
+/// Returns the amount of flattened shapes which fit in a single storage binding
+fn max_shape_capacity(limits: &wgpu::Limits) -> u64 {
+    max_binding_size(limits) / u64::from(ShapeGPU::min_size())
+}
+
+/// Returns how many of the top level shapes fit in capacity flattened shapes
+/// Whole shapes are dropped since a partially flattened shape can not be evaluated
+fn shapes_within_capacity<'a>(shapes: impl IntoIterator<Item = &'a Shape>, capacity: u64) -> usize {
+    let mut total = 0;
+    shapes
+        .into_iter()
+        .take_while(|shape| {
+            total += shape.node_count() as u64;
+            total <= capacity
+        })
+        .count()
+}
+
+fn create_instance_buffer(device: &Device, capacity: u64) -> Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("instance buffer"),
+        size: INSTANCE_STRIDE * capacity,
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+fn create_shape_buffer(device: &Device, capacity: u64) -> Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("shape buffer"),
+        size: u64::from(ShapeGPU::min_size()) * capacity,
+        usage: wgpu::BufferUsages::STORAGE
+            | wgpu::BufferUsages::COPY_DST
+            | wgpu::BufferUsages::COPY_SRC,
+        mapped_at_creation: false,
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+fn create_compute_bind_group(
     device: &Device,
-    surface_config: &SurfaceConfiguration,
+    layout: &BindGroupLayout,
+    input_buffer: &Buffer,
+    global_uniform_buffer: &Buffer,
+    material_buffer: &Buffer,
     texture_view: &TextureView,
-) -> (RenderPipeline, BindGroup) {
-    let diffuse_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
-        address_mode_u: wgpu::AddressMode::ClampToEdge,
+    depth_texture_view: &TextureView,
+    normal_texture_view: &TextureView,
+    id_texture_view: &TextureView,
+    accumulation_buffer: &Buffer,
+    surface_texture_view: &TextureView,
+    surface_sampler: &wgpu::Sampler,
+    environment_texture_view: &TextureView,
+    environment_sampler: &wgpu::Sampler,
+    instance_buffer: &Buffer,
+) -> BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("compute bind group"),
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: input_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: global_uniform_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::TextureView(texture_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: material_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 4,
+                resource: wgpu::BindingResource::TextureView(depth_texture_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 5,
+                resource: wgpu::BindingResource::TextureView(normal_texture_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 12,
+                resource: wgpu::BindingResource::TextureView(id_texture_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 6,
+                resource: accumulation_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 7,
+                resource: wgpu::BindingResource::TextureView(surface_texture_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 8,
+                resource: wgpu::BindingResource::Sampler(surface_sampler),
+            },
+            wgpu::BindGroupEntry {
+                binding: 9,
+                resource: wgpu::BindingResource::TextureView(environment_texture_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 10,
+                resource: wgpu::BindingResource::Sampler(environment_sampler),
+            },
+            wgpu::BindGroupEntry {
+                binding: 11,
+                resource: instance_buffer.as_entire_binding(),
+            },
+        ],
+    })
+}
+
+/// Creates a texture sampled by the compute shader from srgb rgba pixels
+fn create_image_texture(
+    device: &Device,
+    queue: &Queue,
+    label: &str,
+    width: u32,
+    height: u32,
+    rgba: &[u8],
+) -> (Texture, TextureView) {
+    let texture = device.create_texture_with_data(
+        queue,
+        &wgpu::TextureDescriptor {
+            label: Some(label),
+            size: Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            // Srgb so the shader samples linear colors
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        },
+        rgba,
+    );
+    let texture_view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    (texture, texture_view)
+}
+
+/// Creates the repeating sampler of the surface texture
+fn create_surface_sampler(device: &Device) -> wgpu::Sampler {
+    device.create_sampler(&wgpu::SamplerDescriptor {
+        label: Some("surface sampler"),
+        address_mode_u: wgpu::AddressMode::Repeat,
+        address_mode_v: wgpu::AddressMode::Repeat,
+        address_mode_w: wgpu::AddressMode::Repeat,
+        mag_filter: wgpu::FilterMode::Linear,
+        min_filter: wgpu::FilterMode::Linear,
+        ..Default::default()
+    })
+}
+
+/// Creates the sampler of the environment, wrapping around horizontally but not over the poles
+fn create_environment_sampler(device: &Device) -> wgpu::Sampler {
+    device.create_sampler(&wgpu::SamplerDescriptor {
+        label: Some("environment sampler"),
+        address_mode_u: wgpu::AddressMode::Repeat,
         address_mode_v: wgpu::AddressMode::ClampToEdge,
-        address_mode_w: wgpu::AddressMode::ClampToEdge,
-        mag_filter: wgpu::FilterMode::Nearest,
-        min_filter: wgpu::FilterMode::Nearest,
-        mipmap_filter: wgpu::FilterMode::Nearest,
+        mag_filter: wgpu::FilterMode::Linear,
+        min_filter: wgpu::FilterMode::Linear,
         ..Default::default()
+    })
+}
+
+/// Decodes a png file into 8 bit rgba pixels
+/// Returns (width, height, pixels)
+pub(crate) fn load_png(path: &Path) -> Result<(u32, u32, Vec<u8>), RaymarcherError> {
+    let file = File::open(path).map_err(png::DecodingError::from)?;
+    let mut decoder = png::Decoder::new(file);
+    // Palettes are expanded and 16 bit channels reduced to 8 bits
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info()?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buffer)?;
+    let pixels = &buffer[..info.buffer_size()];
+
+    let rgba = match info.color_type {
+        png::ColorType::Rgba => pixels.to_vec(),
+        png::ColorType::Rgb => pixels
+            .chunks_exact(3)
+            .flat_map(|p| [p[0], p[1], p[2], 255])
+            .collect(),
+        png::ColorType::GrayscaleAlpha => pixels
+            .chunks_exact(2)
+            .flat_map(|p| [p[0], p[0], p[0], p[1]])
+            .collect(),
+        // Indexed is expanded to rgb by the transformations
+        png::ColorType::Grayscale | png::ColorType::Indexed => {
+            pixels.iter().flat_map(|&p| [p, p, p, 255]).collect()
+        }
+    };
+    Ok((info.width, info.height, rgba))
+}
+
+/// Creates the texture the compute shader renders into
+fn create_texture(device: &Device, resolution: (u32, u32)) -> (Texture, TextureView) {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("texture desc"),
+        size: Extent3d {
+            width: resolution.0,
+            height: resolution.1,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::Rgba8Unorm,
+        usage: wgpu::TextureUsages::STORAGE_BINDING
+            | wgpu::TextureUsages::COPY_DST
+            | wgpu::TextureUsages::COPY_SRC
+            | wgpu::TextureUsages::TEXTURE_BINDING,
+        view_formats: &[],
+    });
+    let texture_view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    (texture, texture_view)
+}
+
+/// Creates the texture the compute shader writes the hit distance into
+fn create_depth_texture(device: &Device, resolution: (u32, u32)) -> (Texture, TextureView) {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("depth texture"),
+        size: Extent3d {
+            width: resolution.0,
+            height: resolution.1,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::R32Float,
+        usage: wgpu::TextureUsages::STORAGE_BINDING
+            | wgpu::TextureUsages::COPY_SRC
+            | wgpu::TextureUsages::TEXTURE_BINDING,
+        view_formats: &[],
+    });
+    let texture_view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    (texture, texture_view)
+}
+
+/// Creates the texture the compute shader writes the first hit normal into
+fn create_normal_texture(device: &Device, resolution: (u32, u32)) -> (Texture, TextureView) {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("normal texture"),
+        size: Extent3d {
+            width: resolution.0,
+            height: resolution.1,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::Rgba16Float,
+        usage: wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::COPY_SRC,
+        view_formats: &[],
+    });
+    let texture_view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    (texture, texture_view)
+}
+
+/// Creates the texture holding the index of the shape hit by each pixel
+fn create_id_texture(device: &Device, resolution: (u32, u32)) -> (Texture, TextureView) {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("id texture"),
+        size: Extent3d {
+            width: resolution.0,
+            height: resolution.1,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::R32Uint,
+        usage: wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::COPY_SRC,
+        view_formats: &[],
+    });
+    let texture_view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    (texture, texture_view)
+}
+
+/// Creates a texture written by one bloom pass and read by the next
+fn create_bloom_texture(device: &Device, resolution: (u32, u32)) -> TextureView {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("bloom texture"),
+        size: Extent3d {
+            width: resolution.0,
+            height: resolution.1,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::Rgba16Float,
+        usage: wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::TEXTURE_BINDING,
+        view_formats: &[],
     });
+    texture.create_view(&wgpu::TextureViewDescriptor::default())
+}
 
+/// Creates the buffer holding one vec4 running average per pixel
+fn create_accumulation_buffer(device: &Device, resolution: (u32, u32)) -> Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("accumulation buffer"),
+        size: resolution.0 as u64 * resolution.1 as u64 * 16,
+        usage: wgpu::BufferUsages::STORAGE,
+        mapped_at_creation: false,
+    })
+}
+
+/// Converts the bits of a half precision float to f32
+pub(crate) fn f16_to_f32(bits: u16) -> f32 {
+    let sign = if bits & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = ((bits >> 10) & 0x1f) as i32;
+    let mantissa = (bits & 0x3ff) as f32;
+    match exponent {
+        // Subnormal
+        0 => sign * mantissa * 2f32.powi(-24),
+        0x1f if mantissa == 0.0 => sign * f32::INFINITY,
+        0x1f => f32::NAN,
+        _ => sign * (1.0 + mantissa / 1024.0) * 2f32.powi(exponent - 15),
+    }
+}
+
+fn create_render_pipeline(
+    device: &Device,
+    surface_config: &SurfaceConfiguration,
+    post_uniform_buffer: &Buffer,
+    texture_view: &TextureView,
+    depth_texture_view: &TextureView,
+    bloom_view: &TextureView,
+    filter: wgpu::FilterMode,
+) -> (RenderPipeline, BindGroup) {
     let texture_bind_group_layout =
         device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             label: Some("render bind group layout"),
@@ -542,22 +3111,51 @@ fn create_render_pipeline(
                     ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
                     count: None,
                 },
+                // Depth texture, only loaded so no filtering
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    },
+                    count: None,
+                },
+                // Post globals
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                // Bloom texture
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
             ],
         });
-    let texture_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-        layout: &texture_bind_group_layout,
-        entries: &[
-            wgpu::BindGroupEntry {
-                binding: 0,
-                resource: wgpu::BindingResource::TextureView(texture_view),
-            },
-            wgpu::BindGroupEntry {
-                binding: 1,
-                resource: wgpu::BindingResource::Sampler(&diffuse_sampler),
-            },
-        ],
-        label: Some("diffuse bind group"),
-    });
+
+    let texture_bind_group = create_texture_bind_group(
+        device,
+        &texture_bind_group_layout,
+        texture_view,
+        depth_texture_view,
+        bloom_view,
+        post_uniform_buffer,
+        filter,
+    );
 
     let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
         label: Some("Render Pipeline Layout"),
@@ -580,7 +3178,11 @@ fn create_render_pipeline(
         },
         fragment: Some(wgpu::FragmentState {
             module: &shader_module,
-            entry_point: "fs_main",
+            entry_point: if surface_config.format.describe().srgb {
+                "fs_main_srgb"
+            } else {
+                "fs_main"
+            },
             targets: &[Some(wgpu::ColorTargetState {
                 format: surface_config.format,
                 blend: Some(wgpu::BlendState::REPLACE),
@@ -608,6 +3210,64 @@ fn create_render_pipeline(
     (pipeline, texture_bind_group)
 }
 
+fn create_post_uniform_buffer(device: &Device, post_globals: &PostGlobals) -> Buffer {
+    let mut buffer = UniformBuffer::new(Vec::new());
+    buffer.write(post_globals).unwrap();
+    device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("post uniform buffer"),
+        contents: &buffer.into_inner(),
+        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+    })
+}
+
+/// Bind group sampling the render texture, linear filtering smooths upscaling
+fn create_texture_bind_group(
+    device: &Device,
+    layout: &BindGroupLayout,
+    texture_view: &TextureView,
+    depth_texture_view: &TextureView,
+    bloom_view: &TextureView,
+    post_uniform_buffer: &Buffer,
+    filter: wgpu::FilterMode,
+) -> BindGroup {
+    let diffuse_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+        address_mode_u: wgpu::AddressMode::ClampToEdge,
+        address_mode_v: wgpu::AddressMode::ClampToEdge,
+        address_mode_w: wgpu::AddressMode::ClampToEdge,
+        mag_filter: filter,
+        min_filter: filter,
+        mipmap_filter: wgpu::FilterMode::Nearest,
+        ..Default::default()
+    });
+
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(texture_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(&diffuse_sampler),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::TextureView(depth_texture_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: post_uniform_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 4,
+                resource: wgpu::BindingResource::TextureView(bloom_view),
+            },
+        ],
+        label: Some("diffuse bind group"),
+    })
+}
+
 /// Vertex representation
 #[repr(C)]
 #[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
@@ -663,3 +3323,905 @@ fn create_vertex_index_buffers(device: &Device) -> (Buffer, Buffer, u32) {
 
     (vertex_buffer, index_buffer, num_indices)
 }
+
+#[cfg(test)]
+mod tests {
+    use encase::ShaderType;
+    use glam::{vec3, Mat3, Vec3};
+
+    use crate::app::RunConfig;
+    use crate::error::RaymarcherError;
+    use crate::render::{
+        culled, f16_to_f32, indexed_shapes_to_gpu, load_png, look_at_rotation, max_shape_capacity,
+        padded_bytes_per_row, shapes_within_capacity, with_workgroup_size,
+        workgroup_size_supported, Globals, MaterialId, RenderContext, Shape, ShapeGPU, ShapesGPU,
+        StaticShapes, MAX_MANDELBULB_ITERATIONS, MAX_MENGER_ITERATIONS, MAX_SHAPE_DEPTH,
+    };
+
+    fn shapes_to_gpu<'a>(shapes: impl IntoIterator<Item = &'a Shape>) -> ShapesGPU {
+        indexed_shapes_to_gpu(shapes.into_iter().enumerate())
+    }
+
+    #[test]
+    fn f16_to_f32_test() {
+        assert_eq!(f16_to_f32(0x0000), 0.0);
+        assert_eq!(f16_to_f32(0x3c00), 1.0);
+        assert_eq!(f16_to_f32(0xbc00), -1.0);
+        assert_eq!(f16_to_f32(0x3800), 0.5);
+        assert_eq!(f16_to_f32(0x7bff), 65504.0);
+        assert_eq!(f16_to_f32(0x0001), 2f32.powi(-24));
+        assert_eq!(f16_to_f32(0x7c00), f32::INFINITY);
+    }
+
+    #[test]
+    fn padded_bytes_per_row_test() {
+        // Already aligned
+        assert_eq!(padded_bytes_per_row(64, 4), 256);
+        assert_eq!(padded_bytes_per_row(1280, 4), 5120);
+
+        // Needs padding
+        assert_eq!(padded_bytes_per_row(1, 4), 256);
+        assert_eq!(padded_bytes_per_row(65, 4), 512);
+        assert_eq!(padded_bytes_per_row(100, 8), 1024);
+    }
+
+    #[test]
+    fn shape_dist_test() {
+        let sphere = Shape::Sphere {
+            pos: vec3(1.0, 0.0, 0.0),
+            radius: 1.0,
+        };
+        assert_eq!(sphere.dist(vec3(3.0, 0.0, 0.0)), 1.0);
+        assert_eq!(sphere.dist(vec3(1.0, 0.0, 0.0)), -1.0);
+
+        let cube = Shape::BoxExact {
+            pos: vec3(0.0, 0.0, 0.0),
+            b: vec3(1.0, 1.0, 1.0),
+        };
+        assert_eq!(cube.dist(vec3(0.0, 3.0, 0.0)), 2.0);
+        assert_eq!(cube.dist(vec3(0.0, 0.5, 0.0)), -0.5);
+
+        let subtraction = Shape::Subtraction {
+            shape1: Box::new(cube),
+            shape2: Box::new(sphere),
+        };
+        // Inside the sphere is carved out of the box
+        assert!(subtraction.dist(vec3(0.5, 0.0, 0.0)) > 0.0);
+        assert!(subtraction.dist(vec3(-0.5, 0.0, 0.0)) < 0.0);
+    }
+
+    #[test]
+    fn repeat_dist_test() {
+        let repeat = Shape::Repeat {
+            shape: Box::new(Shape::Sphere {
+                pos: vec3(0.0, 0.0, 0.0),
+                radius: 1.0,
+            }),
+            center: Vec3::ZERO,
+            spacing: vec3(4.0, 0.0, 4.0),
+        };
+        // Copies along x and z
+        assert_eq!(repeat.dist(vec3(8.0, 0.0, 0.0)), -1.0);
+        assert_eq!(repeat.dist(vec3(-4.0, 0.0, 12.0)), -1.0);
+        // No repetition along y
+        assert_eq!(repeat.dist(vec3(0.0, 8.0, 0.0)), 7.0);
+    }
+
+    #[test]
+    fn repeat_limited_dist_test() {
+        let repeat = Shape::RepeatLimited {
+            shape: Box::new(Shape::Sphere {
+                pos: vec3(0.0, 0.0, 0.0),
+                radius: 1.0,
+            }),
+            center: Vec3::ZERO,
+            spacing: vec3(4.0, 4.0, 4.0),
+            count: vec3(2.0, 0.0, 2.0),
+        };
+        // Copies within count
+        assert_eq!(repeat.dist(vec3(8.0, 0.0, -8.0)), -1.0);
+        // No copies past count
+        assert_eq!(repeat.dist(vec3(12.0, 0.0, 0.0)), 3.0);
+        assert_eq!(repeat.dist(vec3(0.0, 4.0, 0.0)), 3.0);
+    }
+
+    #[test]
+    fn rotate_dist_test() {
+        let rotate = Shape::Rotate {
+            shape: Box::new(Shape::BoxExact {
+                pos: vec3(0.0, 0.0, 0.0),
+                b: vec3(1.0, 1.0, 1.0),
+            }),
+            rotation: Mat3::from_rotation_z(45f32.to_radians()),
+        };
+        // Corners of the box now point along the axes
+        assert!(rotate.dist(vec3(1.4, 0.0, 0.0)) < 0.0);
+        assert!(rotate.dist(vec3(0.0, 1.4, 0.0)) < 0.0);
+        assert!(rotate.dist(vec3(0.9, 0.9, 0.0)) > 0.0);
+    }
+
+    #[test]
+    fn scale_test() {
+        let scale = Shape::Scale {
+            shape: Box::new(Shape::Sphere {
+                pos: vec3(1.0, 0.0, 0.0),
+                radius: 1.0,
+            }),
+            factor: 2.0,
+        };
+        // Both position and radius are scaled
+        assert_eq!(scale.dist(vec3(2.0, 0.0, 0.0)), -2.0);
+        assert_eq!(scale.dist(vec3(6.0, 0.0, 0.0)), 2.0);
+
+        let gpu_shapes = shapes_to_gpu(&[scale]);
+        assert_eq!(gpu_shapes.0.len(), 2);
+        assert_eq!(gpu_shapes.0[0].id, 12);
+        assert_eq!(gpu_shapes.0[0].f1, 2.0);
+        assert_eq!(gpu_shapes.0[1].id, 6);
+    }
+
+    #[test]
+    fn look_at_rotation_test() {
+        // Looking along +z is the identity
+        let rot = look_at_rotation(vec3(0.0, 0.0, 2.0), vec3(0.0, 1.0, 0.0));
+        assert!(rot.abs_diff_eq(Mat3::IDENTITY, 1e-6));
+
+        // Forward maps to the view direction
+        let dir = vec3(1.0, 0.0, 0.0);
+        let rot = look_at_rotation(dir, vec3(0.0, 1.0, 0.0));
+        assert!((rot * vec3(0.0, 0.0, 1.0)).abs_diff_eq(dir, 1e-6));
+        assert!((rot * vec3(0.0, 1.0, 0.0)).abs_diff_eq(vec3(0.0, 1.0, 0.0), 1e-6));
+
+        // Looking straight up still gives an orthonormal basis
+        let dir = vec3(0.0, 1.0, 0.0);
+        let rot = look_at_rotation(dir, vec3(0.0, 1.0, 0.0));
+        assert!((rot * vec3(0.0, 0.0, 1.0)).abs_diff_eq(dir, 1e-6));
+        assert!((rot.determinant() - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn mandelbulb_test() {
+        let bulb = Shape::Mandelbulb {
+            pos: vec3(1.0, 0.0, 0.0),
+            power: 8.0,
+            iterations: 8,
+        };
+        // Center is inside, far away points are outside
+        assert!(bulb.dist(vec3(1.0, 0.0, 0.0)) <= 0.0);
+        assert!(bulb.dist(vec3(5.0, 0.0, 0.0)) > 1.0);
+
+        let gpu_shapes = shapes_to_gpu(&[bulb]);
+        assert_eq!(gpu_shapes.0.len(), 1);
+        assert_eq!(gpu_shapes.0[0].id, 15);
+        assert_eq!(gpu_shapes.0[0].f1, 8.0);
+        assert_eq!(gpu_shapes.0[0].f2, 8.0);
+
+        let deep = Shape::Mandelbulb {
+            pos: Vec3::ZERO,
+            power: 8.0,
+            iterations: u32::MAX,
+        };
+        assert_eq!(
+            shapes_to_gpu(&[deep]).0[0].f2,
+            MAX_MANDELBULB_ITERATIONS as f32
+        );
+    }
+
+    #[test]
+    fn menger_sponge_test() {
+        let sponge = Shape::MengerSponge {
+            pos: vec3(0.0, 1.0, 0.0),
+            size: 2.0,
+            iterations: 3,
+        };
+        // Outside the bounding cube the distance is exact
+        assert!((sponge.dist(vec3(5.0, 1.0, 0.0)) - 3.0).abs() < 1e-5);
+        // Corners are solid, the center of each face is carved out
+        assert!(sponge.dist(vec3(1.9, 2.9, 1.9)) <= 0.0);
+        assert!(sponge.dist(vec3(0.0, 1.0, 1.9)) > 0.0);
+
+        let gpu_shapes = shapes_to_gpu(&[sponge]);
+        assert_eq!(gpu_shapes.0[0].id, 18);
+        assert_eq!(gpu_shapes.0[0].f1, 2.0);
+        assert_eq!(gpu_shapes.0[0].f2, 3.0);
+
+        let deep = Shape::MengerSponge {
+            pos: Vec3::ZERO,
+            size: 1.0,
+            iterations: u32::MAX,
+        };
+        assert_eq!(shapes_to_gpu(&[deep]).0[0].f2, MAX_MENGER_ITERATIONS as f32);
+    }
+
+    #[test]
+    fn bounds_test() {
+        let sphere = Shape::Sphere {
+            pos: vec3(1.0, 0.0, 0.0),
+            radius: 1.0,
+        };
+        assert_eq!(
+            sphere.bounds(),
+            (vec3(0.0, -1.0, -1.0), vec3(2.0, 1.0, 1.0))
+        );
+
+        let rotated = Shape::Rotate {
+            shape: Box::new(Shape::BoxExact {
+                pos: Vec3::ZERO,
+                b: vec3(2.0, 1.0, 1.0),
+            }),
+            rotation: Mat3::from_rotation_z(std::f32::consts::FRAC_PI_2),
+        };
+        let (min, max) = rotated.bounds();
+        assert!(min.abs_diff_eq(vec3(-1.0, -2.0, -1.0), 1e-5));
+        assert!(max.abs_diff_eq(vec3(1.0, 2.0, 1.0), 1e-5));
+
+        let union = Shape::Union {
+            shape1: Box::new(sphere.clone()),
+            shape2: Box::new(Shape::Repeat {
+                shape: Box::new(sphere.clone()),
+                center: Vec3::ZERO,
+                spacing: vec3(3.0, 0.0, 0.0),
+            }),
+        };
+        let (min, max) = union.bounds();
+        assert_eq!(min, vec3(f32::NEG_INFINITY, -1.0, -1.0));
+        assert_eq!(max, vec3(f32::INFINITY, 1.0, 1.0));
+
+        // Leaves carry their bounds, unbounded leaves are left zero
+        let gpu_shapes = shapes_to_gpu(&[union]);
+        assert_eq!(gpu_shapes.0[1].m1.x_axis, vec3(0.0, -1.0, -1.0));
+        assert_eq!(gpu_shapes.0[1].m1.y_axis, vec3(2.0, 1.0, 1.0));
+        assert_eq!(gpu_shapes.0[3].m1, Mat3::ZERO);
+    }
+
+    #[test]
+    fn bounds_csg_test() {
+        let union = Shape::Union {
+            shape1: Box::new(Shape::Sphere {
+                pos: vec3(-2.0, 0.0, 0.0),
+                radius: 1.0,
+            }),
+            shape2: Box::new(Shape::Sphere {
+                pos: vec3(2.0, 1.0, 0.0),
+                radius: 0.5,
+            }),
+        };
+        assert_eq!(
+            union.bounds(),
+            (vec3(-3.0, -1.0, -1.0), vec3(2.5, 1.5, 1.0))
+        );
+
+        let mut cube = Shape::BoxExact {
+            pos: Vec3::ZERO,
+            b: vec3(1.0, 2.0, 3.0),
+        };
+        cube.translate(vec3(1.0, 1.0, 1.0));
+        assert_eq!(cube.bounds(), (vec3(0.0, -1.0, -2.0), vec3(2.0, 3.0, 4.0)));
+
+        // Planes are unbounded, intersecting with them keeps the other bounds
+        let plane = Shape::Plane {
+            pos: Vec3::ZERO,
+            normal: vec3(0.0, 1.0, 0.0),
+        };
+        assert_eq!(plane.bounds(), (Vec3::NEG_INFINITY, Vec3::INFINITY));
+        let clipped = Shape::Intersection {
+            shape1: Box::new(plane),
+            shape2: Box::new(cube.clone()),
+        };
+        assert_eq!(clipped.bounds(), cube.bounds());
+    }
+
+    #[test]
+    fn custom_test() {
+        let mut custom = Shape::Custom {
+            id: 3,
+            pos: vec3(1.0, 0.0, 0.0),
+            v1: vec3(0.0, 1.0, 0.0),
+            f1: 0.5,
+        };
+        custom.translate(vec3(0.0, 2.0, 0.0));
+
+        let gpu_shapes = shapes_to_gpu(&[custom]);
+        assert_eq!(gpu_shapes.0[0].id, 19);
+        assert_eq!(gpu_shapes.0[0].pos, vec3(1.0, 2.0, 0.0));
+        assert_eq!(gpu_shapes.0[0].v1, vec3(0.0, 1.0, 0.0));
+        assert_eq!(gpu_shapes.0[0].f1, 0.5);
+        assert_eq!(gpu_shapes.0[0].f2.to_bits(), 3);
+
+        let large = Shape::Custom {
+            id: u32::MAX,
+            pos: Vec3::ZERO,
+            v1: Vec3::ZERO,
+            f1: 0.0,
+        };
+        assert_eq!(shapes_to_gpu(&[large]).0[0].f2.to_bits(), u32::MAX);
+    }
+
+    #[test]
+    fn invalid_custom_sdf_fails_to_compile() {
+        let config = RunConfig {
+            custom_sdf: Some("fn sdf_custom(id: u32) -> f32 { return missing; }".to_string()),
+            ..Default::default()
+        };
+        match pollster::block_on(RenderContext::new(None, (8, 8), &config)) {
+            Err(RaymarcherError::ShaderCompilationFailed(_)) => {}
+            // Machines without a gpu can not run this test
+            Err(RaymarcherError::AdapterNotFound) => {}
+            Err(err) => panic!("{err}"),
+            Ok(_) => panic!("invalid custom sdf compiled"),
+        }
+    }
+
+    #[test]
+    fn pyramid_test() {
+        let pyramid = Shape::Pyramid {
+            pos: vec3(0.0, 1.0, 0.0),
+            base: 2.0,
+            height: 1.0,
+        };
+        // Inside, the base is closer than the faces
+        assert!((pyramid.dist(vec3(0.0, 1.1, 0.0)) + 0.1).abs() < 1e-5);
+        // Apex and base
+        assert!((pyramid.dist(vec3(0.0, 3.0, 0.0)) - 1.0).abs() < 1e-5);
+        assert!((pyramid.dist(vec3(0.0, 0.0, 0.0)) - 1.0).abs() < 1e-5);
+        // Beside a base corner
+        assert!((pyramid.dist(vec3(2.0, 1.0, 1.0)) - 1.0).abs() < 1e-5);
+
+        let gpu_shapes = shapes_to_gpu(&[pyramid]);
+        assert_eq!(gpu_shapes.0[0].id, 20);
+        assert_eq!(gpu_shapes.0[0].f1, 2.0);
+        assert_eq!(gpu_shapes.0[0].v1.x, 1.0);
+    }
+
+    #[test]
+    fn link_test() {
+        let link = Shape::Link {
+            pos: vec3(0.0, 1.0, 0.0),
+            length: 2.0,
+            inner_radius: 1.0,
+            tube_radius: 0.25,
+        };
+        // Middle of the tube on a straight side and on a round end
+        assert!((link.dist(vec3(1.0, 1.5, 0.0)) + 0.25).abs() < 1e-5);
+        assert!((link.dist(vec3(0.0, 3.0, 0.0)) + 0.25).abs() < 1e-5);
+        // Center of the hole
+        assert!((link.dist(vec3(0.0, 1.0, 0.0)) - 0.75).abs() < 1e-5);
+
+        // The tube can not be thicker than the hole
+        let clamped = Shape::Link {
+            pos: Vec3::ZERO,
+            length: 0.0,
+            inner_radius: 0.5,
+            tube_radius: 2.0,
+        };
+        assert!(clamped.dist(vec3(2.0, 0.0, 0.0)) > 0.0);
+
+        let gpu_shapes = shapes_to_gpu(&[link, clamped]);
+        assert_eq!(gpu_shapes.0[0].id, 21);
+        assert_eq!(gpu_shapes.0[0].v1, vec3(1.0, 1.0, 0.0));
+        assert_eq!(gpu_shapes.0[0].f1, 0.25);
+        assert_eq!(gpu_shapes.0[1].f1, 0.5);
+    }
+
+    #[test]
+    fn rotate_around_test() {
+        let pos = vec3(3.0, 1.0, -2.0);
+        let rotated = Shape::RotateAround {
+            shape: Box::new(Shape::BoxExact {
+                pos,
+                b: vec3(1.0, 0.5, 0.5),
+            }),
+            center: pos,
+            rotation: Mat3::from_rotation_y(std::f32::consts::FRAC_PI_2),
+        };
+        // Stays in place, only the long axis turns from x to z
+        assert!(rotated.dist(pos) < 0.0);
+        assert!((rotated.dist(pos + vec3(0.0, 0.0, 1.5)) - 0.5).abs() < 1e-5);
+        assert!((rotated.dist(pos + vec3(1.5, 0.0, 0.0)) - 1.0).abs() < 1e-5);
+        let (min, max) = rotated.bounds();
+        assert!((min - (pos - vec3(0.5, 0.5, 1.0))).abs().max_element() < 1e-5);
+        assert!((max - (pos + vec3(0.5, 0.5, 1.0))).abs().max_element() < 1e-5);
+
+        // Translating moves the center along
+        let mut moved = rotated.clone();
+        moved.translate(vec3(1.0, 0.0, 0.0));
+        assert!(moved.dist(pos + vec3(1.0, 0.0, 0.0)) < 0.0);
+        assert!((moved.dist(pos + vec3(1.0, 0.0, 1.5)) - 0.5).abs() < 1e-5);
+
+        let gpu_shapes = shapes_to_gpu(&[rotated]);
+        assert_eq!(gpu_shapes.0[0].id, 23);
+        assert_eq!(gpu_shapes.0[0].v1, pos);
+    }
+
+    #[test]
+    fn orbit_test() {
+        let orbit = Shape::Orbit {
+            shape: Box::new(Shape::Sphere {
+                pos: Vec3::ZERO,
+                radius: 0.5,
+            }),
+            center: vec3(0.0, 1.0, 0.0),
+            radius: 2.0,
+            speed: 1.0,
+        };
+        // Bounds and distance cover every point of the orbit
+        assert_eq!(orbit.bounds(), (vec3(-2.5, 0.5, -2.5), vec3(2.5, 1.5, 2.5)));
+        assert!(orbit.dist(vec3(0.0, 1.0, 2.0)) <= -0.5);
+        assert!((orbit.dist(vec3(0.0, 1.0, 5.0)) - 2.5).abs() < 1e-5);
+
+        let gpu_shapes = shapes_to_gpu(&[orbit]);
+        assert_eq!(gpu_shapes.0[0].id, 22);
+        assert_eq!(gpu_shapes.0[0].v1, vec3(0.0, 1.0, 0.0));
+        assert_eq!(gpu_shapes.0[0].f1, 2.0);
+        assert_eq!(gpu_shapes.0[0].f2, 1.0);
+        assert_eq!(gpu_shapes.0[1].id, 6);
+    }
+
+    #[test]
+    fn disk_test() {
+        let disk = Shape::Disk {
+            pos: vec3(0.0, 1.0, 0.0),
+            normal: vec3(0.0, 2.0, 0.0),
+            radius: 1.0,
+        };
+        // Above the face
+        assert!((disk.dist(vec3(0.5, 3.0, 0.0)) - 2.0).abs() < 1e-6);
+        // Beside the rim
+        assert!((disk.dist(vec3(3.0, 1.0, 0.0)) - 2.0).abs() < 1e-6);
+        // Diagonally past the rim the closest point is the edge
+        assert!((disk.dist(vec3(4.0, 5.0, 0.0)) - 5.0).abs() < 1e-6);
+
+        let gpu_shapes = shapes_to_gpu(&[disk]);
+        assert_eq!(gpu_shapes.0[0].id, 16);
+        assert_eq!(gpu_shapes.0[0].v1, vec3(0.0, 1.0, 0.0));
+        assert_eq!(gpu_shapes.0[0].f1, 1.0);
+    }
+
+    #[test]
+    fn material_test() {
+        let sphere = Shape::Sphere {
+            pos: vec3(0.0, 0.0, 0.0),
+            radius: 1.0,
+        };
+        let shape = Shape::Material {
+            shape: Box::new(Shape::Union {
+                shape1: Box::new(sphere.clone()),
+                shape2: Box::new(Shape::Material {
+                    shape: Box::new(sphere.clone()),
+                    material: MaterialId(2),
+                }),
+            }),
+            material: MaterialId(1),
+        };
+
+        // Inner material takes precedence, shapes without material use the default
+        let gpu_shapes = shapes_to_gpu(&[shape, sphere]);
+        let materials: Vec<u32> = gpu_shapes.0.iter().map(|shape| shape.material).collect();
+        assert_eq!(materials, [0, 1, 2, MaterialId::DEFAULT.0]);
+    }
+
+    #[test]
+    fn bend_test() {
+        let bend = Shape::Bend {
+            shape: Box::new(Shape::BoxExact {
+                pos: vec3(0.0, 0.0, 0.0),
+                b: vec3(2.0, 0.1, 0.1),
+            }),
+            center: Vec3::ZERO,
+            amount: 0.5,
+        };
+        // Points along the bend axis are unaffected apart from the safety scaling
+        assert!((bend.dist(vec3(0.0, 1.1, 0.0)) - 1.0 / 1.5).abs() < 1e-5);
+        // At x = 1 the plane is rotated by 0.5 radians, lifting the point above the box
+        let expected = (0.5f32.sin() - 0.1) / 1.5;
+        assert!((bend.dist(vec3(1.0, 0.0, 0.0)) - expected).abs() < 1e-5);
+
+        let gpu_shapes = shapes_to_gpu(&[bend]);
+        assert_eq!(gpu_shapes.0.len(), 2);
+        assert_eq!(gpu_shapes.0[0].id, 13);
+        assert_eq!(gpu_shapes.0[0].f1, 0.5);
+        assert_eq!(gpu_shapes.0[1].id, 7);
+        assert_eq!(gpu_shapes.0[1].v1, vec3(2.0, 0.1, 0.1));
+    }
+
+    #[test]
+    fn onion_test() {
+        let sphere = Shape::Sphere {
+            pos: vec3(0.0, 0.0, 0.0),
+            radius: 2.0,
+        };
+        let onion = Shape::Onion {
+            shape: Box::new(sphere),
+            thickness: 0.25,
+        };
+        // Hollow inside, shell at the surface
+        assert_eq!(onion.dist(vec3(0.0, 0.0, 0.0)), 1.75);
+        assert_eq!(onion.dist(vec3(2.0, 0.0, 0.0)), -0.25);
+
+        // Nested onions give two concentric shells around the surface
+        let nested = Shape::Onion {
+            shape: Box::new(onion),
+            thickness: 0.1,
+        };
+        assert_eq!(nested.dist(vec3(2.0, 0.0, 0.0)), 0.15);
+        assert_eq!(nested.dist(vec3(2.25, 0.0, 0.0)), -0.1);
+        assert_eq!(nested.dist(vec3(1.75, 0.0, 0.0)), -0.1);
+
+        let gpu_shapes = shapes_to_gpu(&[nested]);
+        let ids: Vec<u32> = gpu_shapes.0.iter().map(|shape| shape.id).collect();
+        assert_eq!(ids, [14, 14, 6]);
+        assert_eq!(gpu_shapes.0[0].f1, 0.1);
+        assert_eq!(gpu_shapes.0[1].f1, 0.25);
+    }
+
+    #[test]
+    fn visible_test() {
+        let sphere = Shape::Sphere {
+            pos: vec3(0.0, 0.0, 0.0),
+            radius: 1.0,
+        };
+        let visible = |visible| Shape::Union {
+            shape1: Box::new(Shape::Visible {
+                shape: Box::new(sphere.clone()),
+                visible,
+            }),
+            shape2: Box::new(Shape::Sphere {
+                pos: vec3(3.0, 0.0, 0.0),
+                radius: 1.0,
+            }),
+        };
+        assert_eq!(visible(true).dist(vec3(0.0, 0.0, 0.0)), -1.0);
+        assert_eq!(visible(false).dist(vec3(0.0, 0.0, 0.0)), 2.0);
+
+        // Hidden shapes keep their slots
+        let shown = shapes_to_gpu(&[visible(true)]);
+        let hidden = shapes_to_gpu(&[visible(false)]);
+        let shown_ids: Vec<u32> = shown.0.iter().map(|shape| shape.id).collect();
+        let hidden_ids: Vec<u32> = hidden.0.iter().map(|shape| shape.id).collect();
+        assert_eq!(shown_ids, [0, 17, 6, 6]);
+        assert_eq!(shown_ids, hidden_ids);
+        assert_eq!(hidden.0[1].f1, 0.0);
+    }
+
+    #[test]
+    fn static_shapes_test() {
+        let sphere = Shape::Sphere {
+            pos: vec3(0.0, 0.0, 0.0),
+            radius: 1.0,
+        };
+        let mut shapes = StaticShapes::default();
+
+        let handle1 = shapes.add(sphere.clone());
+        let handle2 = shapes.add(sphere.clone());
+        assert_eq!(shapes.len(), 2);
+
+        // Removing does not affect other handles
+        assert!(shapes.remove(handle1).is_some());
+        assert!(shapes.remove(handle1).is_none());
+        assert_eq!(shapes.len(), 1);
+        assert_eq!(shapes.iter().count(), 1);
+
+        // Removed slot is reused, old handle stays stale
+        let handle3 = shapes.add(sphere.clone());
+        assert_eq!(shapes.len(), 2);
+        assert!(!shapes.set_translation(handle1, vec3(1.0, 0.0, 0.0)));
+        assert!(!shapes.set(handle1, sphere.clone()));
+        assert!(shapes.remove(handle1).is_none());
+        assert!(shapes.remove(handle2).is_some());
+
+        shapes.clear();
+        assert_eq!(shapes.len(), 0);
+        assert!(shapes.remove(handle3).is_none());
+    }
+
+    #[test]
+    fn static_shapes_translation_test() {
+        let mut shapes = StaticShapes::default();
+        let handle = shapes.add(Shape::Sphere {
+            pos: vec3(1.0, 0.0, 0.0),
+            radius: 1.0,
+        });
+
+        // Translation is relative to the added position, not accumulated
+        assert!(shapes.set_translation(handle, vec3(0.0, 2.0, 0.0)));
+        assert!(shapes.set_translation(handle, vec3(0.0, 4.0, 0.0)));
+        let shape = shapes.iter().next().unwrap();
+        assert_eq!(shape.dist(vec3(1.0, 4.0, 0.0)), -1.0);
+    }
+
+    #[test]
+    fn translate_test() {
+        let mut shape = Shape::Scale {
+            shape: Box::new(Shape::Rotate {
+                shape: Box::new(Shape::Sphere {
+                    pos: vec3(1.0, 0.0, 0.0),
+                    radius: 0.5,
+                }),
+                rotation: Mat3::from_rotation_z(90f32.to_radians()),
+            }),
+            factor: 2.0,
+        };
+        let center = vec3(0.0, 2.0, 0.0);
+        assert!((shape.dist(center) - -1.0).abs() < 1e-5);
+
+        shape.translate(vec3(3.0, 0.0, 0.0));
+        assert!((shape.dist(center + vec3(3.0, 0.0, 0.0)) - -1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn translate_repeat_and_bend_test() {
+        let sphere = Box::new(Shape::Sphere {
+            pos: Vec3::ZERO,
+            radius: 1.0,
+        });
+        // Moving further than half the spacing must not push the copies out of their cells
+        let offset = vec3(3.0, 0.0, 0.0);
+        let mut repeat = Shape::Repeat {
+            shape: sphere.clone(),
+            center: Vec3::ZERO,
+            spacing: vec3(4.0, 0.0, 0.0),
+        };
+        repeat.translate(offset);
+        assert_eq!(repeat.dist(offset), -1.0);
+        assert_eq!(repeat.dist(offset + vec3(8.0, 0.0, 0.0)), -1.0);
+        assert_eq!(shapes_to_gpu(&[repeat]).0[0].pos, offset);
+
+        let mut limited = Shape::RepeatLimited {
+            shape: sphere.clone(),
+            center: Vec3::ZERO,
+            spacing: vec3(4.0, 0.0, 0.0),
+            count: vec3(1.0, 0.0, 0.0),
+        };
+        limited.translate(offset);
+        assert_eq!(limited.dist(offset - vec3(4.0, 0.0, 0.0)), -1.0);
+        assert_eq!(limited.dist(offset + vec3(4.0, 0.0, 0.0)), -1.0);
+        assert_eq!(limited.dist(offset + vec3(8.0, 0.0, 0.0)), 3.0);
+
+        // The bent shape moves unchanged
+        let bend = Shape::Bend {
+            shape: Box::new(Shape::BoxExact {
+                pos: Vec3::ZERO,
+                b: vec3(2.0, 0.1, 0.1),
+            }),
+            center: Vec3::ZERO,
+            amount: 0.5,
+        };
+        let mut moved = bend.clone();
+        moved.translate(offset);
+        for p in [
+            vec3(1.0, 0.0, 0.0),
+            vec3(-1.5, 0.3, 0.0),
+            vec3(0.5, 1.0, 0.2),
+        ] {
+            assert!((moved.dist(p + offset) - bend.dist(p)).abs() < 1e-5);
+        }
+        let (min, max) = bend.bounds();
+        let (moved_min, moved_max) = moved.bounds();
+        assert!(moved_min.abs_diff_eq(min + offset, 1e-5));
+        assert!(moved_max.abs_diff_eq(max + offset, 1e-5));
+    }
+
+    #[test]
+    fn load_png_test() {
+        // 2x1 rgb image, expanded to rgba
+        let path = std::env::temp_dir().join("gpu_raymarcher_load_png_test.png");
+        let file = std::fs::File::create(&path).unwrap();
+        let mut encoder = png::Encoder::new(file, 2, 1);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(&[255, 0, 0, 0, 0, 255]).unwrap();
+        writer.finish().unwrap();
+
+        let (width, height, rgba) = load_png(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!((width, height), (2, 1));
+        assert_eq!(rgba, [255, 0, 0, 255, 0, 0, 255, 255]);
+
+        assert!(matches!(
+            load_png(&path),
+            Err(RaymarcherError::TextureLoadFailed(_))
+        ));
+    }
+
+    #[test]
+    fn node_count_depth() {
+        let sphere = || {
+            Box::new(Shape::Sphere {
+                pos: Vec3::ZERO,
+                radius: 1.0,
+            })
+        };
+        assert_eq!(sphere().node_count(), 1);
+        assert_eq!(sphere().max_depth(), 1);
+
+        // Unbalanced chain of unions
+        let mut shape = *sphere();
+        for _ in 0..4 {
+            shape = Shape::Union {
+                shape1: Box::new(shape),
+                shape2: sphere(),
+            };
+        }
+        assert_eq!(shape.node_count(), 9);
+        assert_eq!(shape.max_depth(), 5);
+        assert_eq!(shapes_to_gpu(vec![&shape]).0.len(), shape.node_count());
+
+        // Materials are not flattened into nodes, other unary operations are
+        let shape = Shape::Material {
+            shape: Box::new(Shape::Scale {
+                shape: Box::new(shape),
+                factor: 2.0,
+            }),
+            material: MaterialId(1),
+        };
+        assert_eq!(shape.node_count(), 10);
+        assert_eq!(shape.max_depth(), 6);
+        assert_eq!(shapes_to_gpu(vec![&shape]).0.len(), shape.node_count());
+    }
+
+    #[test]
+    fn check_depth() {
+        let mut shape = Shape::Sphere {
+            pos: Vec3::ZERO,
+            radius: 1.0,
+        };
+        for _ in 1..MAX_SHAPE_DEPTH {
+            shape = Shape::Onion {
+                shape: Box::new(shape),
+                thickness: 0.1,
+            };
+        }
+        assert!(shape.check_depth().is_ok());
+
+        let shape = Shape::Union {
+            shape1: Box::new(shape.clone()),
+            shape2: Box::new(shape),
+        };
+        assert!(matches!(
+            shape.check_depth(),
+            Err(RaymarcherError::ShapeTooDeep(depth)) if depth == MAX_SHAPE_DEPTH + 1
+        ));
+    }
+
+    #[test]
+    fn leaf_ids_match_shader() {
+        // Terms of the form `id == 15u` or `(id >= 6u && id <= 8u)` joined by ||
+        let source = include_str!("../shaders/compute_shader.wgsl");
+        let start = source.find("fn is_leaf(id: u32) -> bool {").unwrap();
+        let body = &source[start..];
+        let expr = &body[body.find("return ").unwrap() + 7..body.find(';').unwrap()];
+        let parse = |n: &str| n.trim().trim_end_matches('u').parse::<u32>().unwrap();
+        let ranges: Vec<_> = expr
+            .split("||")
+            .map(|term| {
+                let term = term.trim().trim_start_matches('(').trim_end_matches(')');
+                match term.split_once("&&") {
+                    Some((low, high)) => {
+                        parse(low.split(">=").nth(1).unwrap())
+                            ..=parse(high.split("<=").nth(1).unwrap())
+                    }
+                    None => {
+                        let id = parse(term.split("==").nth(1).unwrap());
+                        id..=id
+                    }
+                }
+            })
+            .collect();
+
+        for id in 0..32 {
+            let shape = ShapeGPU {
+                id,
+                ..Default::default()
+            };
+            let in_shader = ranges.iter().any(|range| range.contains(&id));
+            assert_eq!(shape.is_leaf(), in_shader, "id {id}");
+        }
+    }
+
+    #[test]
+    fn post_globals_match_between_shaders() {
+        let post_globals = |source: &'static str| {
+            let start = source.find("struct PostGlobals {").unwrap();
+            let end = start + source[start..].find('}').unwrap();
+            &source[start..end]
+        };
+        assert_eq!(
+            post_globals(include_str!("../shaders/bloom_shader.wgsl")),
+            post_globals(include_str!("../shaders/render_shader.wgsl"))
+        );
+    }
+
+    #[test]
+    fn workgroup_size_test() {
+        let source = with_workgroup_size(include_str!("../shaders/compute_shader.wgsl"), (16, 4));
+        assert!(source.contains("@compute @workgroup_size(16, 4, 1)\nfn cs_main"));
+        assert!(!source.contains("@workgroup_size(1)"));
+    }
+
+    #[test]
+    fn workgroup_size_supported_test() {
+        let limits = wgpu::Limits::default();
+        assert!(workgroup_size_supported((8, 8), &limits));
+        assert!(workgroup_size_supported((16, 16), &limits));
+        assert!(!workgroup_size_supported((0, 8), &limits));
+        assert!(!workgroup_size_supported((32, 32), &limits));
+        assert!(!workgroup_size_supported((1024, 1), &limits));
+        assert!(!workgroup_size_supported((u32::MAX, 2), &limits));
+    }
+
+    #[test]
+    fn shape_capacity_limits() {
+        let shape_size = u64::from(ShapeGPU::min_size());
+        let limits = wgpu::Limits {
+            max_storage_buffer_binding_size: (shape_size * 100) as u32,
+            ..Default::default()
+        };
+        assert_eq!(max_shape_capacity(&limits), 100);
+
+        let sphere = Shape::Sphere {
+            pos: Vec3::ZERO,
+            radius: 1.0,
+        };
+        let union = Shape::Union {
+            shape1: Box::new(sphere.clone()),
+            shape2: Box::new(sphere.clone()),
+        };
+        let shapes = [&sphere, &union, &sphere];
+        assert_eq!(shapes_within_capacity(shapes, 5), 3);
+        // The union does not fit as a whole so it and everything after is dropped
+        assert_eq!(shapes_within_capacity(shapes, 3), 1);
+        assert_eq!(shapes_within_capacity(shapes, 0), 0);
+    }
+
+    #[test]
+    fn instances_test() {
+        let sphere = Shape::Sphere {
+            pos: Vec3::ZERO,
+            radius: 0.5,
+        };
+        let instances = Shape::Instances {
+            shape: Box::new(Shape::Material {
+                shape: Box::new(sphere),
+                material: MaterialId(2),
+            }),
+            offsets: vec![vec3(-2.0, 0.0, 0.0), vec3(3.0, 1.0, 0.0)],
+        };
+        assert!((instances.dist(vec3(3.0, 2.0, 0.0)) - 0.5).abs() < 1e-6);
+        assert_eq!(
+            instances.bounds(),
+            (vec3(-2.5, -0.5, -0.5), vec3(3.5, 1.5, 0.5))
+        );
+        assert_eq!(instances.node_count(), 2);
+        assert_eq!(instances.max_depth(), 1);
+
+        // Offsets of later instances continue after the earlier ones
+        let gpu_shapes = shapes_to_gpu(&[instances.clone(), instances]);
+        assert_eq!(gpu_shapes.0.len(), 4);
+        assert_eq!(gpu_shapes.0[2].id, 24);
+        assert_eq!(gpu_shapes.0[2].f1.to_bits(), 2);
+        assert_eq!(gpu_shapes.0[2].f2.to_bits(), 2);
+        assert_eq!(gpu_shapes.0[3].material, 2);
+        assert_eq!(gpu_shapes.1.len(), 4);
+    }
+
+    #[test]
+    fn culling_keeps_shadow_casters() {
+        // Camera at the origin looking along +z, point light above it
+        let mut g = Globals::new((64, 64));
+        g.light_pos = vec3(0.0, 10.0, 0.0);
+        let unit_box = |center: Vec3| (center - 0.5, center + 0.5);
+
+        assert!(!culled(&g, false, unit_box(vec3(0.0, 0.0, 5.0))));
+        // Behind the camera, away from the light
+        assert!(culled(&g, false, unit_box(vec3(0.0, 0.0, -40.0))));
+        // Above the view but below the light, it can shadow visible surfaces
+        assert!(!culled(&g, false, unit_box(vec3(0.0, 8.0, 3.0))));
+        // Reflections can reach anything within range
+        assert!(!culled(&g, true, unit_box(vec3(0.0, 0.0, -40.0))));
+        // Further than any ray travels
+        assert!(culled(&g, true, unit_box(vec3(0.0, 0.0, -1000.0))));
+        // Inverted bounds are kept like unbounded ones
+        assert!(!culled(&g, false, (Vec3::ONE, -Vec3::ONE)));
+    }
+}
@@ -1,8 +1,21 @@
-use std::time;
+use std::{collections::VecDeque, time};
+
+// Amount of frames used for the rolling frame time average
+const FRAME_HISTORY: usize = 60;
 
 pub struct TimeContext {
     pub(crate) start_time: time::SystemTime,
     pub(crate) current_time: time::SystemTime,
+    // Advances time by a fixed amount each frame instead of using the wall clock
+    pub(crate) fixed_dt: Option<f32>,
+    // Frame limiter, None runs as fast as possible
+    pub(crate) target_fps: Option<u32>,
+    // Durations of the most recent frames
+    pub(crate) frame_times: VecDeque<f32>,
+    // Simulation time sent to the shader, advanced by dt * time_scale unless paused
+    pub(crate) scaled_time: f32,
+    pub(crate) time_scale: f32,
+    pub(crate) paused: bool,
 }
 
 impl Default for TimeContext {
@@ -11,26 +24,145 @@ impl Default for TimeContext {
         Self {
             start_time,
             current_time: start_time,
+            fixed_dt: None,
+            target_fps: None,
+            frame_times: VecDeque::with_capacity(FRAME_HISTORY),
+            scaled_time: 0.0,
+            time_scale: 1.0,
+            paused: false,
         }
     }
 }
 
 impl TimeContext {
     pub(crate) fn update_time(&mut self) -> f32 {
-        let new_time = std::time::SystemTime::now();
+        let new_time = match self.fixed_dt {
+            Some(dt) => self.current_time + time::Duration::from_secs_f32(dt),
+            None => std::time::SystemTime::now(),
+        };
         let dt = new_time
             .duration_since(self.current_time)
             .unwrap()
             .as_secs_f32();
         self.current_time = new_time;
+
+        if self.frame_times.len() == FRAME_HISTORY {
+            self.frame_times.pop_front();
+        }
+        self.frame_times.push_back(dt);
+
+        if !self.paused {
+            self.scaled_time += dt * self.time_scale;
+        }
+
         dt
     }
 
+    /// Restarts the current frame at the current time
+    /// Keeps time spent paused out of the next dt
+    pub(crate) fn skip_time(&mut self) {
+        if self.fixed_dt.is_none() {
+            self.current_time = std::time::SystemTime::now();
+        }
+    }
+
+    /// Returns the average frame time over the most recent frames
+    pub(crate) fn average_frame_time(&self) -> f32 {
+        if self.frame_times.is_empty() {
+            return 0.0;
+        }
+        self.frame_times.iter().sum::<f32>() / self.frame_times.len() as f32
+    }
+
     pub(crate) fn time_since_start(&self) -> f32 {
-        let new_time = std::time::SystemTime::now();
+        let new_time = match self.fixed_dt {
+            Some(_) => self.current_time,
+            None => std::time::SystemTime::now(),
+        };
         new_time
             .duration_since(self.start_time)
             .unwrap()
             .as_secs_f32()
     }
+
+    /// Sleeps the remainder of the frame budget if a target fps is set
+    pub(crate) fn limit_frame_rate(&self) {
+        if let Some(remaining) = self.remaining_frame_time(time::SystemTime::now()) {
+            std::thread::sleep(remaining);
+        }
+    }
+
+    /// Returns the time left of the frame budget measured from the start of the current frame
+    fn remaining_frame_time(&self, now: time::SystemTime) -> Option<time::Duration> {
+        let fps = self.target_fps?;
+        let budget = time::Duration::from_secs_f64(1.0 / fps as f64);
+        let elapsed = now.duration_since(self.current_time).unwrap_or_default();
+        budget.checked_sub(elapsed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::time::{TimeContext, FRAME_HISTORY};
+
+    #[test]
+    fn remaining_frame_time_test() {
+        let mut tc = TimeContext::default();
+        let start = tc.current_time;
+
+        assert_eq!(tc.remaining_frame_time(start), None);
+
+        tc.target_fps = Some(50);
+        let remaining = tc.remaining_frame_time(start + Duration::from_millis(5));
+        assert_eq!(remaining, Some(Duration::from_millis(15)));
+
+        // Frame took longer than the budget
+        let remaining = tc.remaining_frame_time(start + Duration::from_millis(25));
+        assert_eq!(remaining, None);
+    }
+
+    #[test]
+    fn average_frame_time_test() {
+        let mut tc = TimeContext {
+            fixed_dt: Some(0.5),
+            ..Default::default()
+        };
+        assert_eq!(tc.average_frame_time(), 0.0);
+
+        tc.update_time();
+        tc.update_time();
+        assert_eq!(tc.average_frame_time(), 0.5);
+
+        // Only the most recent frames are kept
+        for _ in 0..FRAME_HISTORY * 2 {
+            tc.update_time();
+        }
+        assert_eq!(tc.frame_times.len(), FRAME_HISTORY);
+    }
+
+    #[test]
+    fn scaled_time_test() {
+        let mut tc = TimeContext {
+            fixed_dt: Some(0.5),
+            ..Default::default()
+        };
+
+        tc.time_scale = 2.0;
+        tc.update_time();
+        assert_eq!(tc.scaled_time, 1.0);
+
+        // Paused time stays while real time advances
+        tc.paused = true;
+        tc.update_time();
+        assert_eq!(tc.scaled_time, 1.0);
+        assert_eq!(tc.time_since_start(), 1.0);
+
+        // Negative scale runs backwards
+        tc.paused = false;
+        tc.time_scale = -1.0;
+        tc.update_time();
+        assert_eq!(tc.scaled_time, 0.5);
+    }
 }
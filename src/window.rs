@@ -1,25 +1,37 @@
+use std::time::{Duration, Instant};
+
 use winit::{
     dpi::PhysicalSize,
     event::{DeviceEvent, ElementState, Event, WindowEvent},
     event_loop::{ControlFlow, EventLoop},
-    window::WindowBuilder,
+    window::{Icon, WindowBuilder},
 };
 
 use crate::{
     app::{App, Callbacks},
     context::Context,
-    render::{HEIGHT, WIDTH},
+    error::RaymarcherError,
+    input::InputEvent,
 };
 
-pub(crate) fn new_window() -> (winit::window::Window, winit::event_loop::EventLoop<()>) {
+// Time between updates while the window is minimized or occluded
+const HIDDEN_UPDATE_INTERVAL: Duration = Duration::from_millis(16);
+
+pub(crate) fn new_window(
+    width: u32,
+    height: u32,
+    icon: Option<Icon>,
+) -> Result<(winit::window::Window, winit::event_loop::EventLoop<()>), RaymarcherError> {
     let event_loop = EventLoop::new();
 
     let window = WindowBuilder::new()
-        .with_inner_size(PhysicalSize::new(WIDTH, HEIGHT))
+        .with_title("gpu raymarcher")
+        .with_inner_size(PhysicalSize::new(width, height))
+        .with_window_icon(icon)
         .build(&event_loop)
-        .unwrap();
+        .map_err(RaymarcherError::WindowCreationFailed)?;
 
-    (window, event_loop)
+    Ok((window, event_loop))
 }
 
 pub(crate) async fn run_window<C: Callbacks + 'static>(
@@ -27,71 +39,129 @@ pub(crate) async fn run_window<C: Callbacks + 'static>(
     mut app: App<C>,
     mut ctx: Context,
 ) {
+    let window_id = ctx.render.window.as_ref().map(|window| window.id());
+
     event_loop.run(move |event, _, control_flow| match event {
         Event::WindowEvent {
             ref event,
-            window_id,
-        } => {
-            if window_id == ctx.render.window.id() {
-                match event {
-                    WindowEvent::CloseRequested => *control_flow = ControlFlow::Exit,
-                    WindowEvent::Resized(physical_size) => {
-                        ctx.render.resize_window(*physical_size);
-                    }
-                    WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
-                        ctx.render.resize_window(**new_inner_size);
-                    }
-                    WindowEvent::CursorMoved { position, .. } => {
-                        ctx.input.mouse.set_pos(position.x, position.y, &ctx.render);
-                    }
-                    WindowEvent::MouseInput { state, button, .. } => match state {
-                        ElementState::Pressed => ctx.input.mouse.press_button(*button),
-                        ElementState::Released => ctx.input.mouse.release_button(*button),
-                    },
-                    WindowEvent::CursorLeft { .. } => {
-                        ctx.input.mouse.set_on_screen(false);
-                    }
-                    WindowEvent::MouseWheel { delta, .. } => {
-                        let (x, y) = match delta {
-                            winit::event::MouseScrollDelta::LineDelta(x, y) => {
-                                (*x as f64, *y as f64)
-                            }
-                            winit::event::MouseScrollDelta::PixelDelta(pos) => (pos.x, pos.y),
-                        };
-                        ctx.input.mouse.set_scroll_delta((x, y));
-                    }
-                    WindowEvent::KeyboardInput { input, .. } => {
-                        if let Some(keycode) = input.virtual_keycode {
-                            match input.state {
-                                ElementState::Pressed => ctx.input.keyboard.set_key(keycode),
-                                ElementState::Released => ctx.input.keyboard.release_key(keycode),
-                            }
+            window_id: id,
+        } if Some(id) == window_id => match event {
+            WindowEvent::CloseRequested => *control_flow = ControlFlow::Exit,
+            WindowEvent::Focused(focused) => {
+                if ctx.paused() && *focused {
+                    ctx.time.skip_time();
+                }
+                ctx.focused = *focused;
+            }
+            WindowEvent::Resized(physical_size) => {
+                // Minimizing resizes to 0x0 on some platforms
+                ctx.minimized = physical_size.width == 0 || physical_size.height == 0;
+                ctx.render.resize_window(*physical_size);
+                if !ctx.minimized {
+                    app.callbacks
+                        .resize(&mut ctx, physical_size.width, physical_size.height);
+                }
+            }
+            WindowEvent::Occluded(occluded) => ctx.occluded = *occluded,
+            WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
+                ctx.render.resize_window(**new_inner_size);
+                if new_inner_size.width > 0 && new_inner_size.height > 0 {
+                    app.callbacks
+                        .resize(&mut ctx, new_inner_size.width, new_inner_size.height);
+                }
+            }
+            WindowEvent::CursorMoved { position, .. } => {
+                ctx.input.mouse.set_pos(position.x, position.y, &ctx.render);
+                ctx.input
+                    .push_event(InputEvent::MouseMoved((position.x, position.y)));
+            }
+            WindowEvent::MouseInput { state, button, .. } => match state {
+                ElementState::Pressed => {
+                    ctx.input.mouse.press_button(*button);
+                    ctx.input
+                        .push_event(InputEvent::MouseButtonPressed(*button));
+                }
+                ElementState::Released => {
+                    ctx.input.mouse.release_button(*button);
+                    ctx.input
+                        .push_event(InputEvent::MouseButtonReleased(*button));
+                }
+            },
+            WindowEvent::CursorLeft { .. } => {
+                ctx.input.mouse.set_on_screen(false);
+            }
+            WindowEvent::MouseWheel { delta, .. } => {
+                let (x, y) = match delta {
+                    winit::event::MouseScrollDelta::LineDelta(x, y) => (*x as f64, *y as f64),
+                    winit::event::MouseScrollDelta::PixelDelta(pos) => (pos.x, pos.y),
+                };
+                ctx.input.mouse.set_scroll_delta((x, y));
+                ctx.input.push_event(InputEvent::MouseScrolled((x, y)));
+            }
+            WindowEvent::KeyboardInput { input, .. } => {
+                if let Some(keycode) = input.virtual_keycode {
+                    match input.state {
+                        ElementState::Pressed => {
+                            ctx.input.keyboard.set_key(keycode);
+                            ctx.input.push_event(InputEvent::KeyPressed(keycode));
+                        }
+                        ElementState::Released => {
+                            ctx.input.keyboard.release_key(keycode);
+                            ctx.input.push_event(InputEvent::KeyReleased(keycode));
                         }
                     }
-                    WindowEvent::ModifiersChanged(modifiers) => {
-                        ctx.input.keyboard.modifiers_changed(*modifiers)
-                    }
-                    _ => {}
                 }
             }
-        }
-        Event::DeviceEvent { ref event, .. } => match event {
-            DeviceEvent::MouseMotion { delta } => ctx.input.mouse.set_mouse_delta(*delta),
+            WindowEvent::ModifiersChanged(modifiers) => {
+                ctx.input.keyboard.modifiers_changed(*modifiers)
+            }
             _ => {}
         },
-        Event::RedrawRequested(window_id) if window_id == ctx.render.window.id() => {
+        Event::DeviceEvent {
+            event: DeviceEvent::MouseMotion { delta },
+            ..
+        } => ctx.input.mouse.set_mouse_delta(delta),
+        Event::RedrawRequested(id) if Some(id) == window_id && !ctx.paused() && !ctx.hidden() => {
             match ctx.render.render(&ctx.time) {
-                Ok(_) => {}
-                Err(wgpu::SurfaceError::Lost) => ctx.render.resize_window(ctx.render.window_size),
+                Ok(_) => ctx.record_frame(),
+                // Reconfigure to the current size, outdated surfaces occur after rapid resizes or
+                // when moving between displays
+                Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
+                    ctx.render.resize_window(ctx.render.window_size)
+                }
                 Err(wgpu::SurfaceError::OutOfMemory) => *control_flow = ControlFlow::Exit,
                 Err(e) => eprintln!("{:?}", e),
             }
         }
+        // Sent once after ControlFlow::Exit is set, regardless of what caused the exit
+        Event::LoopDestroyed => app.callbacks.exit(&mut ctx),
         Event::MainEventsCleared => {
+            // Sleep until the next event while paused
+            // Mouse motion is dropped so the camera does not jump when resuming
+            if ctx.paused() {
+                ctx.input.mouse.reset_mouse_delta();
+                ctx.input.mouse.reset_scroll_delta();
+                *control_flow = ControlFlow::Wait;
+                return;
+            }
+            // Surface textures can not be acquired while minimized, so nothing is presented and
+            // nothing blocks on vsync. Updates are spaced out instead of spinning
+            *control_flow = if ctx.hidden() {
+                ControlFlow::WaitUntil(Instant::now() + HIDDEN_UPDATE_INTERVAL)
+            } else {
+                ControlFlow::Poll
+            };
+            ctx.time.limit_frame_rate();
             if app.update(&mut ctx) {
                 *control_flow = ControlFlow::Exit;
+                return;
+            }
+            if ctx.hidden() {
+                return;
+            }
+            if let Some(window) = &ctx.render.window {
+                window.request_redraw();
             }
-            ctx.render.window.request_redraw();
         }
         _ => {}
     });
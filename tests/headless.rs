@@ -0,0 +1,286 @@
+use std::sync::{
+    atomic::{AtomicU32, Ordering},
+    Arc, Mutex,
+};
+
+use glam::{vec3, Vec3};
+use gpu_raymarcher::{cmd::render, Callbacks, Context, RaymarcherError, RunConfig, Shape};
+
+const WIDTH: u32 = 32;
+const HEIGHT: u32 = 32;
+
+struct SphereScene;
+
+/// Sphere rendered with jittered antialiasing samples
+struct JitteredScene {
+    seed: u32,
+}
+
+impl Callbacks for JitteredScene {
+    fn init(&self, ctx: &mut Context) {
+        render::set_antialiasing(ctx, 4);
+        render::set_seed(ctx, self.seed);
+    }
+
+    fn update(&mut self, ctx: &mut Context, dt: f32) -> bool {
+        SphereScene.update(ctx, dt)
+    }
+}
+
+impl Callbacks for SphereScene {
+    fn update(&mut self, ctx: &mut Context, _dt: f32) -> bool {
+        render::set_camera_pos(ctx, vec3(0.0, 0.0, -3.0));
+        render::render_shape(
+            ctx,
+            Shape::Sphere {
+                pos: vec3(0.0, 0.0, 0.0),
+                radius: 1.0,
+            },
+        );
+        false
+    }
+}
+
+/// Sphere recorded for a few frames into dir
+struct RecordedScene {
+    dir: std::path::PathBuf,
+}
+
+impl Callbacks for RecordedScene {
+    fn init(&self, ctx: &mut Context) {
+        render::start_recording(ctx, &self.dir, 3, Some(1.0 / 30.0)).unwrap();
+    }
+
+    fn update(&mut self, ctx: &mut Context, dt: f32) -> bool {
+        SphereScene.update(ctx, dt)
+    }
+}
+
+/// Row of spheres, either instanced or rendered one by one
+struct SphereRowScene {
+    instanced: bool,
+}
+
+impl Callbacks for SphereRowScene {
+    fn update(&mut self, ctx: &mut Context, _dt: f32) -> bool {
+        render::set_camera_pos(ctx, vec3(0.0, 0.0, -4.0));
+        let sphere = |pos| Shape::Sphere { pos, radius: 0.5 };
+        let offsets = [
+            vec3(-1.5, 0.0, 0.0),
+            vec3(0.0, 0.0, 0.0),
+            vec3(1.5, 0.0, 0.0),
+        ];
+        if self.instanced {
+            render::render_instanced(ctx, sphere(Vec3::ZERO), &offsets);
+        } else {
+            for offset in offsets {
+                render::render_shape(ctx, sphere(offset));
+            }
+        }
+        false
+    }
+}
+
+/// Exits on the first update and counts exit calls
+struct ExitingScene {
+    exits: Arc<AtomicU32>,
+}
+
+impl Callbacks for ExitingScene {
+    fn update(&mut self, _ctx: &mut Context, _dt: f32) -> bool {
+        true
+    }
+
+    fn exit(&mut self, _ctx: &mut Context) {
+        self.exits.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Sphere submitted after a shape behind the camera, picks the corner and center on exit
+struct PickedScene {
+    picks: Arc<Mutex<Vec<Option<u32>>>>,
+}
+
+impl Callbacks for PickedScene {
+    fn update(&mut self, ctx: &mut Context, dt: f32) -> bool {
+        render::render_shape(
+            ctx,
+            Shape::Sphere {
+                pos: vec3(0.0, 0.0, -10.0),
+                radius: 1.0,
+            },
+        );
+        SphereScene.update(ctx, dt)
+    }
+
+    fn exit(&mut self, ctx: &mut Context) {
+        let ids = render::read_ids(ctx);
+        assert_eq!(ids.len(), (WIDTH * HEIGHT) as usize);
+        let mut picks = self.picks.lock().unwrap();
+        picks.push(render::pick_gpu(ctx, 0, 0));
+        picks.push(render::pick_gpu(ctx, WIDTH / 2, HEIGHT / 2));
+        picks.push(render::pick_gpu(ctx, WIDTH, 0));
+    }
+}
+
+/// Sphere whose normals are read back on exit
+struct NormalScene {
+    normals: Arc<Mutex<Vec<[f32; 4]>>>,
+}
+
+impl Callbacks for NormalScene {
+    fn update(&mut self, ctx: &mut Context, dt: f32) -> bool {
+        SphereScene.update(ctx, dt)
+    }
+
+    fn exit(&mut self, ctx: &mut Context) {
+        *self.normals.lock().unwrap() = render::read_normals(ctx);
+    }
+}
+
+/// Runs the app headless for frames and returns the last frame
+/// None when the machine has no gpu to run the test on
+fn run_or_skip<C: Callbacks + 'static>(callbacks: C, frames: u32) -> Option<Vec<[u8; 4]>> {
+    match gpu_raymarcher::run_headless(callbacks, WIDTH, HEIGHT, frames) {
+        Ok(pixels) => Some(pixels),
+        Err(RaymarcherError::AdapterNotFound) => None,
+        Err(err) => panic!("{err}"),
+    }
+}
+
+fn pixel(pixels: &[[u8; 4]], x: u32, y: u32) -> [u8; 4] {
+    pixels[(y * WIDTH + x) as usize]
+}
+
+#[test]
+fn sphere_center_pixel_is_lit() {
+    let Some(pixels) = run_or_skip(SphereScene, 1) else {
+        return;
+    };
+    assert_eq!(pixels.len(), (WIDTH * HEIGHT) as usize);
+
+    let center = pixel(&pixels, WIDTH / 2, HEIGHT / 2);
+    assert!(center[..3].iter().any(|c| *c > 0), "center: {center:?}");
+}
+
+#[test]
+fn same_seed_renders_identical_frames() {
+    let render = |seed| run_or_skip(JitteredScene { seed }, 1);
+    let Some(first) = render(7) else {
+        return;
+    };
+    assert_eq!(Some(&first), render(7).as_ref());
+    // Jitter moves edge samples, so another seed changes some pixels
+    assert_ne!(Some(&first), render(8).as_ref());
+}
+
+#[test]
+fn recording_writes_numbered_frames() {
+    let dir = std::env::temp_dir().join("gpu_raymarcher_recording_test");
+    let _ = std::fs::remove_dir_all(&dir);
+    let scene = RecordedScene { dir: dir.clone() };
+    if run_or_skip(scene, 5).is_none() {
+        return;
+    }
+
+    // Recording stops by itself after the requested frames
+    let mut files: Vec<_> = std::fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect();
+    files.sort();
+    assert_eq!(
+        files,
+        ["frame_00000.png", "frame_00001.png", "frame_00002.png"]
+    );
+
+    let decoder = png::Decoder::new(std::fs::File::open(dir.join(&files[0])).unwrap());
+    let info = decoder.read_info().unwrap().info().clone();
+    assert_eq!((info.width, info.height), (WIDTH, HEIGHT));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn instanced_shapes_match_separate_shapes() {
+    let render = |instanced| run_or_skip(SphereRowScene { instanced }, 1);
+    let Some(separate) = render(false) else {
+        return;
+    };
+    assert_eq!(Some(separate), render(true));
+}
+
+#[test]
+fn exit_is_called_once() {
+    let exits = Arc::default();
+    let scene = ExitingScene {
+        exits: Arc::clone(&exits),
+    };
+    if run_or_skip(scene, 5).is_none() {
+        return;
+    }
+    assert_eq!(exits.load(Ordering::Relaxed), 1);
+}
+
+#[test]
+fn pick_gpu_returns_shape_index() {
+    let picks = Arc::default();
+    let scene = PickedScene {
+        picks: Arc::clone(&picks),
+    };
+    if run_or_skip(scene, 1).is_none() {
+        return;
+    }
+    // Corner misses, the center hits the second shape and out of bounds pixels are ignored
+    assert_eq!(*picks.lock().unwrap(), [None, Some(1), None]);
+}
+
+#[test]
+fn normals_of_first_hit_are_written() {
+    let normals = Arc::default();
+    let scene = NormalScene {
+        normals: Arc::clone(&normals),
+    };
+    if run_or_skip(scene, 1).is_none() {
+        return;
+    }
+    let normals = normals.lock().unwrap();
+    // The center of the sphere faces the camera, the corner misses
+    let center = normals[(HEIGHT / 2 * WIDTH + WIDTH / 2) as usize];
+    assert!(center[2] < -0.9 && center[3] == 1.0, "center: {center:?}");
+    assert_eq!(normals[0], [0.0; 4]);
+}
+
+#[test]
+fn config_is_used_headless() {
+    let run = |config| gpu_raymarcher::run_headless_with_config(SphereScene, config, 1);
+    let config = RunConfig {
+        width: WIDTH,
+        height: HEIGHT,
+        workgroup_size: (16, 4),
+        ..Default::default()
+    };
+    let pixels = match run(config) {
+        Ok(pixels) => pixels,
+        // Machines without a gpu can not run this test
+        Err(RaymarcherError::AdapterNotFound) => return,
+        Err(err) => panic!("{err}"),
+    };
+    assert_eq!(Some(pixels), run_or_skip(SphereScene, 1));
+
+    let invalid_sdf = RunConfig {
+        custom_sdf: Some("fn sdf_custom() -> f32 { return missing; }".to_string()),
+        ..Default::default()
+    };
+    assert!(matches!(
+        run(invalid_sdf),
+        Err(RaymarcherError::ShaderCompilationFailed(_))
+    ));
+    let invalid_workgroup = RunConfig {
+        workgroup_size: (0, 8),
+        ..Default::default()
+    };
+    assert!(matches!(
+        run(invalid_workgroup),
+        Err(RaymarcherError::InvalidWorkgroupSize((0, 8)))
+    ));
+}